@@ -0,0 +1,70 @@
+//! Register preservation across syscalls.
+//!
+//! The kernel returns the result in a0 and must keep a1-a7, tp and usp,
+//! and resume right after the ecall. Each completed event keeps both the
+//! IN and OUT heads, so any difference can be pointed out.
+
+use std::fmt::{Display, Formatter};
+use crate::event::{SigStage, TraceEvent};
+use crate::sysno::*;
+
+/// Size of the ecall instruction.
+pub(crate) const ECALL_SIZE: u64 = 4;
+
+/// A register which the kernel has not preserved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Clobber {
+    pub reg: String,
+    /// Value on IN, or the expected value for epc
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl Display for Clobber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {:#x} -> {:#x}", self.reg, self.expected, self.actual)
+    }
+}
+
+/// Whether IN and OUT of the event are expected to differ.
+fn is_exempt(evt: &TraceEvent) -> bool {
+    if !matches!(evt.signal, SigStage::Empty) || evt.is_trap() {
+        return true;
+    }
+    match evt.head.ax[7] {
+        // The OUT of the child is another task.
+        SYS_CLONE | SYS_CLONE3 => evt.result == 0,
+        SYS_EXECVE => evt.result == 0,
+        SYS_RT_SIGRETURN => true,
+        _ => false,
+    }
+}
+
+/// Registers clobbered by the syscall, empty if the OUT head is unknown.
+pub fn check_event(evt: &TraceEvent) -> Vec<Clobber> {
+    let out = match &evt.out_head {
+        Some(out) if !is_exempt(evt) => out,
+        _ => return vec![],
+    };
+    let head = &evt.head;
+    let mut clobbers = vec![];
+    for i in 1..8 {
+        if head.ax[i] != out.ax[i] {
+            clobbers.push(Clobber {
+                reg: format!("a{}", i),
+                expected: head.ax[i],
+                actual: out.ax[i],
+            });
+        }
+    }
+    for (reg, expected, actual) in [
+        ("tp", head.tp, out.tp),
+        ("usp", head.usp, out.usp),
+        ("epc", head.epc + ECALL_SIZE, out.epc),
+    ] {
+        if expected != actual {
+            clobbers.push(Clobber { reg: reg.to_string(), expected, actual });
+        }
+    }
+    clobbers
+}
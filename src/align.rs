@@ -0,0 +1,382 @@
+//! Alignment of the events of two tasks.
+//!
+//! Events are compared by [`EventKey`]: syscall name, decoded arguments
+//! (normalized by the level of the events) and result, each as selected by
+//! [`EventEq`]. Equivalent variants of a syscall are compared as their
+//! canonical call (see [`equiv`](crate::equiv)). The alignment is a
+//! shortest edit script (Myers); in a run of deleted and inserted events,
+//! those of the same syscall are paired into changes.
+
+use std::borrow::Cow;
+use crate::decode::{decode_event, SysResult};
+use crate::equiv::{canonical, canonical_pair};
+use crate::event::{SigStage, TraceEvent};
+use crate::signal::sig_name;
+use crate::trap::{cause_name, stval};
+
+/// How results are compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResultCmp {
+    Ignore,
+    /// Success, or the errno
+    #[default]
+    Class,
+    /// Rendered result
+    Exact,
+}
+
+/// Equality of events for alignment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventEq {
+    /// Compare decoded arguments
+    pub args: bool,
+    pub result: ResultCmp,
+    /// Compare equivalent syscalls as their canonical call
+    pub equiv: bool,
+}
+
+impl Default for EventEq {
+    fn default() -> Self {
+        Self {
+            args: true,
+            result: ResultCmp::Class,
+            equiv: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResultKey {
+    /// Not compared, or no result
+    None,
+    Ok,
+    Err(i32),
+    Exact(String),
+}
+
+/// What is compared of an event.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EventKey {
+    /// Syscall, trap cause or `signal`
+    pub name: String,
+    pub args: Vec<String>,
+    pub result: ResultKey,
+    /// Signal entered, or which has interrupted the syscall
+    pub signal: Option<u64>,
+}
+
+impl EventEq {
+    /// The event as compared: its canonical call, if `equiv`.
+    pub fn canonical<'a>(&self, evt: &'a TraceEvent) -> Cow<'a, TraceEvent> {
+        if self.equiv {
+            canonical(evt)
+        } else {
+            Cow::Borrowed(evt)
+        }
+    }
+
+    /// Two events as compared: their canonical calls if `equiv` and they
+    /// are different variants.
+    pub fn canonical_pair<'a>(
+        &self, left: &'a TraceEvent, right: &'a TraceEvent
+    ) -> (Cow<'a, TraceEvent>, Cow<'a, TraceEvent>) {
+        if self.equiv {
+            canonical_pair(left, right)
+        } else {
+            (Cow::Borrowed(left), Cow::Borrowed(right))
+        }
+    }
+
+    pub fn key(&self, evt: &TraceEvent) -> EventKey {
+        let signal = match evt.signal {
+            SigStage::Enter(signo) => {
+                return EventKey {
+                    name: "signal".to_string(),
+                    args: vec![sig_name(signo)],
+                    result: ResultKey::None,
+                    signal: Some(signo),
+                };
+            },
+            SigStage::Exit(signo) => Some(signo),
+            SigStage::Empty => None,
+        };
+        if evt.is_trap() {
+            let args = match stval(evt) {
+                Some(stval) if self.args => vec![format!("{:#x}", stval)],
+                _ => vec![],
+            };
+            return EventKey {
+                name: cause_name(evt.head.cause),
+                args,
+                result: ResultKey::None,
+                signal,
+            };
+        }
+        let call = decode_event(&self.canonical(evt));
+        let args = if self.args {
+            call.args.iter().map(|arg| arg.value.to_string()).collect()
+        } else {
+            vec![]
+        };
+        let result = match self.result {
+            ResultCmp::Ignore => ResultKey::None,
+            ResultCmp::Class => match call.result {
+                SysResult::Ok { .. } => ResultKey::Ok,
+                SysResult::Err { errno, .. } => ResultKey::Err(errno),
+            },
+            ResultCmp::Exact => ResultKey::Exact(call.result.text().to_string()),
+        };
+        EventKey {
+            name: call.name,
+            args,
+            result,
+            signal,
+        }
+    }
+}
+
+/// An edit operation, by event indexes in the left and right tasks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditOp {
+    Equal(usize, usize),
+    /// Only on the left
+    Delete(usize),
+    /// Only on the right
+    Insert(usize),
+    /// Same syscall, different arguments or result
+    Change(usize, usize),
+}
+
+/// Align the events of two tasks, e.g. Linux on the left and lkmodel on the
+/// right.
+pub fn align(left: &[TraceEvent], right: &[TraceEvent], eq: &EventEq) -> Vec<EditOp> {
+    let left = left.iter().map(|evt| eq.key(evt)).collect::<Vec<_>>();
+    let right = right.iter().map(|evt| eq.key(evt)).collect::<Vec<_>>();
+    align_keys(&left, &right)
+}
+
+pub fn align_keys(left: &[EventKey], right: &[EventKey]) -> Vec<EditOp> {
+    let mut ops = vec![];
+    let mut dels = vec![];
+    let mut inss = vec![];
+    for op in shortest_edit(left, right) {
+        match op {
+            EditOp::Delete(i) => dels.push(i),
+            EditOp::Insert(j) => inss.push(j),
+            _ => {
+                pair_changes(left, right, &mut dels, &mut inss, &mut ops);
+                ops.push(op);
+            },
+        }
+    }
+    pair_changes(left, right, &mut dels, &mut inss, &mut ops);
+    ops
+}
+
+/// Pair a run of deletes and inserts by syscall name.
+fn pair_changes(
+    left: &[EventKey], right: &[EventKey],
+    dels: &mut Vec<usize>, inss: &mut Vec<usize>, ops: &mut Vec<EditOp>
+) {
+    let del_names = dels.iter().map(|&i| &left[i].name).collect::<Vec<_>>();
+    let ins_names = inss.iter().map(|&j| &right[j].name).collect::<Vec<_>>();
+    for op in shortest_edit(&del_names, &ins_names) {
+        ops.push(match op {
+            EditOp::Equal(i, j) => EditOp::Change(dels[i], inss[j]),
+            EditOp::Delete(i) => EditOp::Delete(dels[i]),
+            EditOp::Insert(j) => EditOp::Insert(inss[j]),
+            EditOp::Change(..) => unreachable!(),
+        });
+    }
+    dels.clear();
+    inss.clear();
+}
+
+/// Myers' O((N+M)D) shortest edit script, with Equal, Delete and Insert.
+/// Linear space: each middle snake splits the script in two halves.
+pub fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Vec<EditOp> {
+    let mut ops = vec![];
+    edit_range(a, b, 0, 0, &mut ops);
+    ops
+}
+
+/// Script of `a` and `b`, which are at `x0` and `y0` of the whole sequences.
+fn edit_range<T: PartialEq>(a: &[T], b: &[T], x0: usize, y0: usize, ops: &mut Vec<EditOp>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    ops.extend((0..prefix).map(|i| EditOp::Equal(x0 + i, y0 + i)));
+    let (a, b, x0, y0) = (&a[prefix..], &b[prefix..], x0 + prefix, y0 + prefix);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (n, m) = (a.len() - suffix, b.len() - suffix);
+    let (a, b) = (&a[..n], &b[..m]);
+
+    if n == 0 {
+        ops.extend((0..m).map(|j| EditOp::Insert(y0 + j)));
+    } else if m == 0 {
+        ops.extend((0..n).map(|i| EditOp::Delete(x0 + i)));
+    } else {
+        // Both halves have fewer edits, as a and b differ at both ends.
+        let (x, y, u, v) = middle_snake(a, b);
+        edit_range(&a[..x], &b[..y], x0, y0, ops);
+        ops.extend((0..u - x).map(|i| EditOp::Equal(x0 + x + i, y0 + y + i)));
+        edit_range(&a[u..], &b[v..], x0 + u, y0 + v, ops);
+    }
+    ops.extend((0..suffix).map(|i| EditOp::Equal(x0 + n + i, y0 + m + i)));
+}
+
+/// The snake (x, y) -> (u, v) in the middle of a shortest path, found by
+/// searching from both ends until the paths overlap.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let at = |k: isize| (k + max + 1) as usize;
+    // Furthest x on diagonal k from the start, and from the end on
+    // diagonal k of the reversed sequences.
+    let mut fwd = vec![0isize; 2 * max as usize + 3];
+    let mut bwd = vec![0isize; 2 * max as usize + 3];
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && fwd[at(k - 1)] < fwd[at(k + 1)]) {
+                fwd[at(k + 1)]
+            } else {
+                fwd[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x_start, y_start) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            fwd[at(k)] = x;
+            let rk = delta - k;
+            if odd && (-(d - 1)..=d - 1).contains(&rk) && x + bwd[at(rk)] >= n {
+                return (x_start as usize, y_start as usize, x as usize, y as usize);
+            }
+        }
+        for rk in (-d..=d).step_by(2) {
+            let mut x = if rk == -d || (rk != d && bwd[at(rk - 1)] < bwd[at(rk + 1)]) {
+                bwd[at(rk + 1)]
+            } else {
+                bwd[at(rk - 1)] + 1
+            };
+            let mut y = x - rk;
+            let (x_start, y_start) = (x, y);
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            bwd[at(rk)] = x;
+            let k = delta - rk;
+            if !odd && (-d..=d).contains(&k) && fwd[at(k)] + x >= n {
+                return ((n - x) as usize, (m - y) as usize, (n - x_start) as usize, (m - y_start) as usize);
+            }
+        }
+    }
+    unreachable!("no middle snake")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Length of the longest common subsequence, by dynamic programming.
+    fn lcs(a: &[u8], b: &[u8]) -> usize {
+        let mut dp = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                dp[i + 1][j + 1] = if a[i] == b[j] {
+                    dp[i][j] + 1
+                } else {
+                    dp[i][j + 1].max(dp[i + 1][j])
+                };
+            }
+        }
+        dp[a.len()][b.len()]
+    }
+
+    /// The script walks both sequences in order and is shortest.
+    fn check(a: &[u8], b: &[u8]) {
+        let ops = shortest_edit(a, b);
+        let (mut i, mut j, mut equal) = (0, 0, 0);
+        for op in &ops {
+            match *op {
+                EditOp::Equal(x, y) => {
+                    assert_eq!((x, y), (i, j), "{:?} {:?}: {:?}", a, b, ops);
+                    assert_eq!(a[x], b[y]);
+                    i += 1;
+                    j += 1;
+                    equal += 1;
+                },
+                EditOp::Delete(x) => {
+                    assert_eq!(x, i, "{:?} {:?}: {:?}", a, b, ops);
+                    i += 1;
+                },
+                EditOp::Insert(y) => {
+                    assert_eq!(y, j, "{:?} {:?}: {:?}", a, b, ops);
+                    j += 1;
+                },
+                EditOp::Change(..) => panic!("change in a shortest edit"),
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        assert_eq!(equal, lcs(a, b), "{:?} {:?}: {:?}", a, b, ops);
+    }
+
+    #[test]
+    fn edit_trivial() {
+        check(b"", b"");
+        check(b"abc", b"");
+        check(b"", b"abc");
+        check(b"abc", b"abc");
+        assert_eq!(shortest_edit(b"ab", b"ab"), vec![EditOp::Equal(0, 0), EditOp::Equal(1, 1)]);
+    }
+
+    #[test]
+    fn edit_myers_example() {
+        check(b"abcabba", b"cbabac");
+        let ops = shortest_edit(b"abcabba", b"cbabac");
+        let edits = ops.iter().filter(|op| !matches!(op, EditOp::Equal(..))).count();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn edit_random() {
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+        for _ in 0..2000 {
+            let a = (0..next(12)).map(|_| b'a' + next(3) as u8).collect::<Vec<_>>();
+            let b = (0..next(12)).map(|_| b'a' + next(3) as u8).collect::<Vec<_>>();
+            check(&a, &b);
+        }
+    }
+
+    #[test]
+    fn edit_long_runs() {
+        let a = [b"x".repeat(300), b"ab".repeat(200), b"y".repeat(300)].concat();
+        let b = [b"z".repeat(250), b"ba".repeat(200), b"x".repeat(10)].concat();
+        check(&a, &b);
+    }
+
+    #[test]
+    fn changes_are_paired_by_name() {
+        let key = |name: &str, arg: &str| EventKey {
+            name: name.to_string(),
+            args: vec![arg.to_string()],
+            result: ResultKey::Ok,
+            signal: None,
+        };
+        let left = [key("openat", "a"), key("read", "3"), key("close", "3")];
+        let right = [key("openat", "b"), key("close", "3")];
+        assert_eq!(align_keys(&left, &right), vec![
+            EditOp::Change(0, 0),
+            EditOp::Delete(1),
+            EditOp::Equal(2, 1),
+        ]);
+    }
+}
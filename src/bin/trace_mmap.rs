@@ -1,13 +1,11 @@
 use std::env;
 use std::fs;
 use std::fs::File;
-use std::io::BufReader;
 use std::io::Result;
 use std::io::Write;
-use std::sync::Arc;
 use std::collections::HashMap;
-use lkdiff::OUT;
-use lkdiff::event::{LK_MAGIC, TE_SIZE, parse_event};
+use lkdiff::event::{TraceEvent, TraceReader};
+use lkdiff::flow::{FlowBuilder, FlowObserver};
 use lkdiff::sysno::{SYS_MMAP, SYS_MUNMAP, SYS_OPENAT, SYS_CLOSE};
 
 fn main() {
@@ -22,52 +20,38 @@ fn main() {
     parse_file(rfile).expect("reference is a bad file.");
 }
 
-fn parse_file(fname: &str) -> Result<()> {
-    let f = File::open(fname)?;
-    let mut filesize = f.metadata().unwrap().len() as usize;
-    let mut reader = BufReader::new(f);
+/// Write mmap related syscalls into one flow file per address space.
+#[derive(Default)]
+struct MmapRecorder {
+    out_map: HashMap<u64, File>,
+}
 
-    let mut out_map: HashMap<u64, Arc<File>> = HashMap::new();
-    while filesize >= TE_SIZE {
-        let mut evt = parse_event(&mut reader, 0)?;
-        let advance = evt.head.totalsize as usize;
-        assert_eq!(evt.head.magic, LK_MAGIC);
-        assert_eq!(evt.head.headsize, TE_SIZE as u16);
-        assert!(evt.head.totalsize >= evt.head.headsize as u32);
-        if evt.head.inout != OUT {
-            filesize -= advance;
-            continue;
+impl FlowObserver for MmapRecorder {
+    fn on_syscall(&mut self, _tid: u64, evt: &TraceEvent) {
+        let sysno = evt.head.ax[7];
+        if sysno != SYS_MMAP && sysno != SYS_MUNMAP && sysno != SYS_OPENAT && sysno != SYS_CLOSE {
+            return;
         }
-
-        evt.result = evt.head.ax[0] as i64;
-        evt.head.ax[0] = evt.head.orig_a0;
-
-        let mut file = if let Some(f) = out_map.get(&evt.head.satp) {
-            f.clone()
-        } else {
+        let file = self.out_map.entry(evt.head.satp).or_insert_with(|| {
             let path = format!("/tmp/mmap_cases/{:#x}.flow", evt.head.satp);
-            let f = Arc::new(File::create(&path).unwrap());
-            out_map.insert(evt.head.satp, f.clone());
-            f
-        };
-        /*
-        println!("tid: {:#x} -> ({})[{:#x}, {:#x}, {}]",
-            evt.head.sscratch, evt.head.inout,
-            evt.head.cause, evt.head.epc, evt.head.ax[7]);
-        */
+            File::create(&path).unwrap()
+        });
 
-        if evt.head.ax[7] == SYS_MMAP || evt.head.ax[7] == SYS_MUNMAP ||
-            evt.head.ax[7] == SYS_OPENAT || evt.head.ax[7] == SYS_CLOSE {
-            if evt.head.ax[7] == SYS_MMAP {
-                evt.raw_fmt = true;
-            }
-            let record = format!("{}\n", evt);
-            let record = record.replace(r"->", "|");
-            let record = record.replace(&['(', ')', ','], "|");
-            let _ = file.write_all(record.as_bytes());
+        let mut evt = evt.clone();
+        if sysno == SYS_MMAP {
+            evt.raw_fmt = true;
         }
-        filesize -= advance;
+        let record = format!("{}\n", evt);
+        let record = record.replace(r"->", "|");
+        let record = record.replace(['(', ')', ','], "|");
+        let _ = file.write_all(record.as_bytes());
     }
+}
 
+fn parse_file(fname: &str) -> Result<()> {
+    let mut builder = FlowBuilder::with_observer(MmapRecorder::default());
+    for evt in TraceReader::open(fname, 0)? {
+        builder.push(evt?);
+    }
     Ok(())
 }
@@ -0,0 +1,234 @@
+//! Syscall decoder registry.
+//!
+//! Every syscall is decoded by a [`SyscallDecoder`] looked up by
+//! (arch, sysno). The built-in decoders cover the Linux syscalls known by
+//! this crate; tools may register decoders for lkmodel-specific or vendor
+//! syscalls, or override a built-in one, with [`register_decoder`].
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+use once_cell::sync::Lazy;
+use crate::event::{TraceEvent, DecodeFn, SYSCALL_DECODERS};
+use crate::profile;
+
+/// Largest errno the kernel returns as -errno.
+pub(crate) const MAX_ERRNO: i64 = 4095;
+
+static REGISTRY: Lazy<RwLock<DecoderRegistry>> = Lazy::new(|| RwLock::new(DecoderRegistry::new()));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Arch {
+    #[default]
+    Riscv64,
+}
+
+/// A decoded argument.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgValue {
+    /// Not decoded, shown as the register value in hex.
+    Raw(u64),
+    Null,
+    Int(i64),
+    /// Quoted string from a payload.
+    Str(String),
+    /// Symbolic value: flags, enum names, AT_FDCWD, masked ids.
+    Symbol(String),
+    /// Struct or array from a payload.
+    Struct(String),
+}
+
+impl Display for ArgValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgValue::Raw(raw) => write!(f, "{:#x}", raw),
+            ArgValue::Null => write!(f, "NULL"),
+            ArgValue::Int(val) => write!(f, "{}", val),
+            ArgValue::Str(s) | ArgValue::Symbol(s) | ArgValue::Struct(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedArg {
+    /// Register value
+    pub raw: u64,
+    pub value: ArgValue,
+}
+
+/// A decoded syscall result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SysResult {
+    Ok { raw: i64, text: String },
+    Err { errno: i32, text: String },
+}
+
+impl SysResult {
+    /// A result is an error iff it is in [-4095, -1], as in the kernel.
+    pub fn new(raw: i64, text: String) -> Self {
+        if (-MAX_ERRNO..0).contains(&raw) {
+            SysResult::Err { errno: -raw as i32, text }
+        } else {
+            SysResult::Ok { raw, text }
+        }
+    }
+
+    pub fn raw(&self) -> i64 {
+        match self {
+            SysResult::Ok { raw, .. } => *raw,
+            SysResult::Err { errno, .. } => -(*errno as i64),
+        }
+    }
+
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            SysResult::Ok { .. } => None,
+            SysResult::Err { errno, .. } => Some(*errno),
+        }
+    }
+
+    pub fn is_err(&self) -> bool {
+        matches!(self, SysResult::Err { .. })
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            SysResult::Ok { text, .. } | SysResult::Err { text, .. } => text,
+        }
+    }
+}
+
+impl Display for SysResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedCall {
+    pub name: String,
+    pub args: Vec<DecodedArg>,
+    pub result: SysResult,
+}
+
+impl DecodedCall {
+    /// Undecoded call: `sys_<n>` with all 7 args in hex.
+    pub fn raw(evt: &TraceEvent) -> Self {
+        Self {
+            name: format!("sys_{}", evt.head.ax[7]),
+            args: evt.head.ax[..7].iter().map(|&raw| {
+                DecodedArg { raw, value: ArgValue::Raw(raw) }
+            }).collect(),
+            result: SysResult::new(evt.result, format!("{:#x}", evt.result)),
+        }
+    }
+}
+
+impl DecodedCall {
+    /// `name(args)` without the result.
+    pub fn signature(&self) -> String {
+        let args = self.args.iter()
+            .map(|arg| arg.value.to_string())
+            .collect::<Vec<_>>();
+        format!("{}({})", self.name, args.join(", "))
+    }
+}
+
+impl Display for DecodedCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.signature(), self.result)
+    }
+}
+
+pub trait SyscallDecoder: Send + Sync {
+    fn decode(&self, evt: &TraceEvent) -> DecodedCall;
+}
+
+/// Adapter of the built-in `do_*` decoders.
+struct BuiltinDecoder(DecodeFn);
+
+impl SyscallDecoder for BuiltinDecoder {
+    fn decode(&self, evt: &TraceEvent) -> DecodedCall {
+        let mut args = evt.head.ax[..7]
+            .iter()
+            .map(|&raw| ArgValue::Raw(raw))
+            .collect::<Vec<_>>();
+        let (name, argc, result) = (self.0)(evt, &mut args);
+        DecodedCall {
+            name: name.to_string(),
+            args: args.into_iter().take(argc).zip(evt.head.ax).map(|(value, raw)| {
+                DecodedArg { raw, value }
+            }).collect(),
+            result: SysResult::new(evt.result, result),
+        }
+    }
+}
+
+pub struct DecoderRegistry {
+    decoders: HashMap<(Arch, u64), Arc<dyn SyscallDecoder>>,
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecoderRegistry {
+    /// A registry with the built-in decoders.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        for (sysno, decode) in SYSCALL_DECODERS {
+            registry.register(Arch::Riscv64, *sysno, BuiltinDecoder(*decode));
+        }
+        registry
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            decoders: HashMap::new(),
+        }
+    }
+
+    /// Register a decoder, returning the one it replaces.
+    pub fn register<D: SyscallDecoder + 'static>(
+        &mut self, arch: Arch, sysno: u64, decoder: D
+    ) -> Option<Arc<dyn SyscallDecoder>> {
+        self.decoders.insert((arch, sysno), Arc::new(decoder))
+    }
+
+    pub fn unregister(&mut self, arch: Arch, sysno: u64) -> Option<Arc<dyn SyscallDecoder>> {
+        self.decoders.remove(&(arch, sysno))
+    }
+
+    pub fn get(&self, arch: Arch, sysno: u64) -> Option<Arc<dyn SyscallDecoder>> {
+        self.decoders.get(&(arch, sysno)).cloned()
+    }
+
+    /// Decode with the registered decoder, or as `sys_<n>` if none.
+    pub fn decode(&self, arch: Arch, evt: &TraceEvent) -> DecodedCall {
+        match self.decoders.get(&(arch, evt.head.ax[7])) {
+            Some(decoder) => decoder.decode(evt),
+            None => DecodedCall::raw(evt),
+        }
+    }
+}
+
+/// Register a decoder into the global registry used by `TraceEvent`'s Display.
+pub fn register_decoder<D: SyscallDecoder + 'static>(
+    arch: Arch, sysno: u64, decoder: D
+) -> Option<Arc<dyn SyscallDecoder>> {
+    REGISTRY.write().unwrap().register(arch, sysno, decoder)
+}
+
+/// Decode with the global registry, hiding the fields of the profile.
+pub fn decode_event(evt: &TraceEvent) -> DecodedCall {
+    // Release the lock before decoding, so a decoder may use the registry.
+    let decoder = REGISTRY.read().unwrap().get(evt.arch(), evt.head.ax[7]);
+    let mut call = match decoder {
+        Some(decoder) => decoder.decode(evt),
+        None => DecodedCall::raw(evt),
+    };
+    profile::normalize(&mut call);
+    call
+}
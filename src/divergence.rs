@@ -0,0 +1,575 @@
+//! Classification of the divergences between Linux and lkmodel.
+//!
+//! The events of each pair of matched tasks are aligned; every edit, and
+//! every task without a pair, is a divergence of one class, so that a diff
+//! tells which kind of bug has been hit. Traps are left out unless asked,
+//! since page faults and interrupts come at different points on each
+//! kernel; if kept, they are compared by cause, so that e.g. an illegal
+//! instruction which Linux turns into SIGILL and lkmodel does not diverges.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::mem;
+use crate::align::{align_keys, EditOp, EventEq};
+use crate::decode::{decode_event, DecodedCall};
+use crate::event::{SigStage, TraceEvent};
+use crate::flow::{ExitReason, TraceFlow};
+use crate::matching::{task_paths, TaskMatch};
+use crate::signal::sig_name;
+use crate::trap::trap_name;
+use crate::OUT;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DivergenceClass {
+    /// Only in Linux
+    MissingSyscall,
+    /// Only in lkmodel
+    ExtraSyscall,
+    Errno,
+    SuccessFailure,
+    ReturnValue,
+    Argument,
+    OutPayload,
+    Signal,
+    /// Trap only on one side, or at another address
+    Trap,
+    TaskStructure,
+}
+
+impl DivergenceClass {
+    pub const ALL: [DivergenceClass; 10] = [
+        DivergenceClass::MissingSyscall,
+        DivergenceClass::ExtraSyscall,
+        DivergenceClass::Errno,
+        DivergenceClass::SuccessFailure,
+        DivergenceClass::ReturnValue,
+        DivergenceClass::Argument,
+        DivergenceClass::OutPayload,
+        DivergenceClass::Signal,
+        DivergenceClass::Trap,
+        DivergenceClass::TaskStructure,
+    ];
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DivergenceClass::MissingSyscall => "missing_syscall",
+            DivergenceClass::ExtraSyscall => "extra_syscall",
+            DivergenceClass::Errno => "errno",
+            DivergenceClass::SuccessFailure => "success_failure",
+            DivergenceClass::ReturnValue => "return_value",
+            DivergenceClass::Argument => "argument",
+            DivergenceClass::OutPayload => "out_payload",
+            DivergenceClass::Signal => "signal",
+            DivergenceClass::Trap => "trap",
+            DivergenceClass::TaskStructure => "task_structure",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DivergenceClass::MissingSyscall => "missing syscall",
+            DivergenceClass::ExtraSyscall => "extra syscall",
+            DivergenceClass::Errno => "different errno",
+            DivergenceClass::SuccessFailure => "success vs failure",
+            DivergenceClass::ReturnValue => "different return value",
+            DivergenceClass::Argument => "different argument",
+            DivergenceClass::OutPayload => "different OUT payload",
+            DivergenceClass::Signal => "different signal behavior",
+            DivergenceClass::Trap => "different trap",
+            DivergenceClass::TaskStructure => "different task structure",
+        }
+    }
+}
+
+impl Display for DivergenceClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A task, or an event of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Site {
+    /// Index of the flow, ordered by start
+    pub task: usize,
+    pub name: String,
+    /// Position in the process tree, see [`task_paths`]
+    pub path: String,
+    /// Index of the event in the flow
+    pub index: Option<usize>,
+    /// File offset of the event
+    pub offset: Option<u64>,
+}
+
+impl Display for Site {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Task[{}]", self.name)?;
+        if let Some(index) = self.index {
+            write!(f, " [{}]", index)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub class: DivergenceClass,
+    /// Linux side
+    pub left: Option<Site>,
+    /// lkmodel side
+    pub right: Option<Site>,
+    /// Syscall of the events, if any
+    pub syscall: Option<String>,
+    /// What differs: `result`, `arg<n>`, `signal`, `trap`, `clone_flags` or
+    /// `exit`;
+    /// none for a whole event or task
+    pub field: Option<String>,
+    /// The field, or the event, on either side
+    pub left_value: Option<String>,
+    pub right_value: Option<String>,
+    pub detail: String,
+}
+
+impl Divergence {
+    /// `left <-> right`, `-` for a side without the task or event.
+    pub fn sites(&self) -> String {
+        let site = |site: &Option<Site>| match site {
+            Some(site) => site.to_string(),
+            None => "-".to_string(),
+        };
+        format!("{} <-> {}", site(&self.left), site(&self.right))
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.class, self.sites(), self.detail)
+    }
+}
+
+/// A difference found in a pair of tasks or events.
+struct Found {
+    class: DivergenceClass,
+    syscall: Option<String>,
+    field: Option<String>,
+    values: (Option<String>, Option<String>),
+}
+
+impl Found {
+    fn field(class: DivergenceClass, field: &str, left: String, right: String) -> Self {
+        Self { class, syscall: None, field: Some(field.to_string()), values: (Some(left), Some(right)) }
+    }
+}
+
+/// Divergences of two traces, Linux on the left, in order of the left tasks.
+/// Traps are compared if `traps`.
+pub fn diff_tasks(left: &[TraceFlow], right: &[TraceFlow], matched: &TaskMatch, traps: bool) -> Vec<Divergence> {
+    let mut tids = HashMap::new();
+    for &(l, r, _) in &matched.pairs {
+        if let (Some(ltid), Some(rtid)) = (left[l].kernel_tid, right[r].kernel_tid) {
+            tids.insert(ltid, rtid);
+        }
+    }
+    let left = Side { flows: left, paths: task_paths(left) };
+    let right = Side { flows: right, paths: task_paths(right) };
+    let mut divs = vec![];
+    for &(l, r, _) in &matched.pairs {
+        diff_pair(&left, l, &right, r, &tids, traps, &mut divs);
+    }
+    for (only, side, is_left) in [(&matched.left_only, &left, true), (&matched.right_only, &right, false)] {
+        for &i in only {
+            let site = side.site(i, None);
+            let (left, right, name) = if is_left { (site, None, "Linux") } else { (None, site, "lkmodel") };
+            divs.push(Divergence {
+                class: DivergenceClass::TaskStructure,
+                left,
+                right,
+                syscall: None,
+                field: None,
+                left_value: None,
+                right_value: None,
+                detail: format!("task only in {}", name),
+            });
+        }
+    }
+    divs
+}
+
+/// Flows of a trace with their paths in the process tree.
+struct Side<'a> {
+    flows: &'a [TraceFlow],
+    paths: Vec<String>,
+}
+
+impl Side<'_> {
+    fn site(&self, task: usize, index: Option<usize>) -> Option<Site> {
+        let flow = &self.flows[task];
+        Some(Site {
+            task,
+            name: flow.name(),
+            path: self.paths[task].clone(),
+            index,
+            offset: index.map(|index| flow.events[index].offset),
+        })
+    }
+}
+
+/// Events compared: all, or without traps which are not signal stages
+/// unless `traps`.
+pub(crate) fn compared(flow: &TraceFlow, traps: bool) -> Vec<usize> {
+    flow.events.iter().enumerate()
+        .filter(|(_, evt)| traps || !evt.is_trap() || !matches!(evt.signal, SigStage::Empty))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Tids of the left trace to those of the matched right tasks.
+type TidMap = HashMap<i64, i64>;
+
+fn diff_pair(
+    lside: &Side, l: usize, rside: &Side, r: usize,
+    tids: &TidMap, traps: bool, divs: &mut Vec<Divergence>
+) {
+    let (left, right) = (&lside.flows[l], &rside.flows[r]);
+    let mut push = |found: Found, li: Option<usize>, rj: Option<usize>, detail: String| {
+        divs.push(Divergence {
+            class: found.class,
+            left: lside.site(l, li),
+            right: rside.site(r, rj),
+            syscall: found.syscall,
+            field: found.field,
+            left_value: found.values.0,
+            right_value: found.values.1,
+            detail,
+        });
+    };
+    if left.clone_flags != right.clone_flags {
+        let (lflags, rflags) = (flags_name(left.clone_flags), flags_name(right.clone_flags));
+        let detail = format!("clone flags {} vs {}", lflags, rflags);
+        push(Found::field(DivergenceClass::TaskStructure, "clone_flags", lflags, rflags), None, None, detail);
+    }
+    let same_exit = match (&left.exit, &right.exit) {
+        (Some(left), Some(right)) => mem::discriminant(left) == mem::discriminant(right),
+        (left, right) => left.is_none() && right.is_none(),
+    };
+    if !same_exit {
+        let (lexit, rexit) = (exit_name(left.exit), exit_name(right.exit));
+        let detail = format!("exit {} vs {}", lexit, rexit);
+        push(Found::field(DivergenceClass::TaskStructure, "exit", lexit, rexit), None, None, detail);
+    }
+
+    let eq = EventEq::default();
+    let left_events = compared(left, traps);
+    let right_events = compared(right, traps);
+    let left_keys = left_events.iter().map(|&i| eq.key(&left.events[i])).collect::<Vec<_>>();
+    let right_keys = right_events.iter().map(|&j| eq.key(&right.events[j])).collect::<Vec<_>>();
+    for op in align_keys(&left_keys, &right_keys) {
+        let (found, li, rj) = match op {
+            EditOp::Delete(i) => {
+                let evt = &left.events[left_events[i]];
+                (only_one(evt, DivergenceClass::MissingSyscall, true), Some(left_events[i]), None)
+            },
+            EditOp::Insert(j) => {
+                let evt = &right.events[right_events[j]];
+                (only_one(evt, DivergenceClass::ExtraSyscall, false), None, Some(right_events[j]))
+            },
+            // Equal events may still return different values.
+            EditOp::Change(i, j) | EditOp::Equal(i, j) => {
+                let (li, rj) = (left_events[i], right_events[j]);
+                match classify(&eq, &left.events[li], &right.events[rj], tids) {
+                    Some(found) => (found, Some(li), Some(rj)),
+                    None => continue,
+                }
+            },
+        };
+        let detail = match (li, rj) {
+            (Some(li), Some(rj)) => format!("{} vs {}", describe(&left.events[li]), describe(&right.events[rj])),
+            (Some(li), None) => describe(&left.events[li]),
+            (None, Some(rj)) => describe(&right.events[rj]),
+            (None, None) => unreachable!(),
+        };
+        push(found, li, rj, detail);
+    }
+}
+
+/// An event on one side only: a signal delivered, a trap, or a syscall.
+fn only_one(evt: &TraceEvent, class: DivergenceClass, is_left: bool) -> Found {
+    let (class, syscall, field, value) = if let SigStage::Enter(signo) = evt.signal {
+        (DivergenceClass::Signal, None, Some("signal".to_string()), sig_name(signo))
+    } else if evt.is_trap() {
+        (DivergenceClass::Trap, None, Some("trap".to_string()), trap_name(evt))
+    } else {
+        (class, Some(decode_event(evt).name), None, describe(evt))
+    };
+    let values = if is_left { (Some(value), None) } else { (None, Some(value)) };
+    Found { class, syscall, field, values }
+}
+
+/// The decoded call, or the event as printed for traps and signal stages.
+fn describe(evt: &TraceEvent) -> String {
+    match evt.signal {
+        SigStage::Empty if !evt.is_trap() => decode_event(evt).to_string(),
+        _ => evt.to_string(),
+    }
+}
+
+fn flags_name(flags: Option<u64>) -> String {
+    match flags {
+        Some(flags) => format!("{:#x}", flags),
+        None => "none".to_string(),
+    }
+}
+
+fn exit_name(exit: Option<ExitReason>) -> String {
+    match exit {
+        Some(exit) => format!("{:?}", exit),
+        None => "none".to_string(),
+    }
+}
+
+fn signal_name(signal: &SigStage) -> String {
+    match signal {
+        SigStage::Empty => "none".to_string(),
+        SigStage::Enter(signo) => format!("enter {}", sig_name(*signo)),
+        SigStage::Exit(signo) => format!("exit {}", sig_name(*signo)),
+    }
+}
+
+/// The most significant difference between two events of the same syscall
+/// or trap cause, if any, as compared by `eq`. Tids of matched tasks are
+/// the same.
+fn classify(eq: &EventEq, left: &TraceEvent, right: &TraceEvent, tids: &TidMap) -> Option<Found> {
+    if left.signal != right.signal {
+        let found = Found::field(DivergenceClass::Signal, "signal", signal_name(&left.signal), signal_name(&right.signal));
+        return Some(found);
+    }
+    if left.is_trap() || right.is_trap() {
+        let (ltrap, rtrap) = (trap_name(left), trap_name(right));
+        let differs = left.head.cause != right.head.cause || (eq.args && ltrap != rtrap);
+        return differs.then(|| Found::field(DivergenceClass::Trap, "trap", ltrap, rtrap));
+    }
+    let (left, right) = eq.canonical_pair(left, right);
+    let (left, right) = (&*left, &*right);
+    let (lcall, rcall) = (decode_event(left), decode_event(right));
+    let result = |class| {
+        let found = Found::field(class, "result", lcall.result.text().to_string(), rcall.result.text().to_string());
+        Some(Found { syscall: Some(lcall.name.clone()), ..found })
+    };
+    match (lcall.result.errno(), rcall.result.errno()) {
+        (Some(lerr), Some(rerr)) if lerr != rerr => return result(DivergenceClass::Errno),
+        (Some(_), None) | (None, Some(_)) => return result(DivergenceClass::SuccessFailure),
+        _ => {},
+    }
+    let same_tid = |lraw: i64, rraw: i64| tids.get(&lraw) == Some(&rraw);
+    // Arguments which carry an OUT payload are output.
+    let is_out = |index| {
+        [left, right].iter().any(|evt| {
+            evt.payloads.iter().any(|payload| payload.inout == OUT && payload.index == index)
+        })
+    };
+    let diffs = (0..lcall.args.len().max(rcall.args.len())).filter(|&i| {
+        match (lcall.args.get(i), rcall.args.get(i)) {
+            (Some(larg), Some(rarg)) => {
+                larg.value != rarg.value && !same_tid(larg.raw as i64, rarg.raw as i64)
+            },
+            _ => true,
+        }
+    }).collect::<Vec<_>>();
+    let arg = |class, i: usize| {
+        let value = |call: &DecodedCall| call.args.get(i).map(|arg| arg.value.to_string());
+        Some(Found {
+            class,
+            syscall: Some(lcall.name.clone()),
+            field: Some(format!("arg{}", i)),
+            values: (value(&lcall), value(&rcall)),
+        })
+    };
+    if let Some(&i) = diffs.iter().find(|&&i| !is_out(i)) {
+        arg(DivergenceClass::Argument, i)
+    } else if let Some(&i) = diffs.first() {
+        arg(DivergenceClass::OutPayload, i)
+    } else if lcall.result.text() != rcall.result.text()
+        && !same_tid(lcall.result.raw(), rcall.result.raw()) {
+        result(DivergenceClass::ReturnValue)
+    } else {
+        None
+    }
+}
+
+/// Print the summary table, with the first divergence of each class.
+pub fn print_summary(divs: &[Divergence]) {
+    println!("Divergences: {}", divs.len());
+    println!("{:<28}{:>6}  first", "class", "count");
+    for class in DivergenceClass::ALL {
+        let mut of_class = divs.iter().filter(|div| div.class == class);
+        let first = of_class.next();
+        let count = first.map_or(0, |_| 1 + of_class.count());
+        let first = first.map_or("-".to_string(), |first| first.sites());
+        println!("{:<28}{:>6}  {}", class.name(), count, first);
+    }
+    for class in DivergenceClass::ALL {
+        if let Some(first) = divs.iter().find(|div| div.class == class) {
+            println!("First {}", first);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errno::{EINVAL, ENOENT};
+    use crate::event::{TracePayload, USER_ECALL};
+    use crate::sysno::*;
+    use crate::IN;
+    use crate::trap::{ILLEGAL_INSN, LOAD_PAGE_FAULT};
+
+    const SIGILL: u64 = 4;
+    const SIGUSR1: u64 = 10;
+
+    fn call(sysno: u64, args: &[u64], result: i64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = OUT;
+        evt.head.cause = USER_ECALL;
+        evt.head.ax[7] = sysno;
+        evt.head.ax[..args.len()].copy_from_slice(args);
+        evt.out_head = Some(evt.head.clone());
+        evt.result = result;
+        evt
+    }
+
+    /// read of stdin, which shows what it has read.
+    fn read(data: &str) -> TraceEvent {
+        let mut evt = call(SYS_READ, &[0, 0x6000, 10], data.len() as i64);
+        let data = format!("{}\0", data).into_bytes();
+        evt.payloads.push(TracePayload { inout: OUT, index: 1, data });
+        evt
+    }
+
+    fn trap(cause: u64, stval: u64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = OUT;
+        evt.head.cause = cause;
+        evt.payloads.push(TracePayload { inout: IN, index: 0, data: stval.to_ne_bytes().to_vec() });
+        evt
+    }
+
+    fn flow(tid: u64, events: Vec<TraceEvent>) -> TraceFlow {
+        let mut flow = TraceFlow::new();
+        flow.tid = tid;
+        flow.events = events;
+        flow
+    }
+
+    /// Divergences of two single tasks paired with each other.
+    fn diff(left: Vec<TraceEvent>, right: Vec<TraceEvent>, traps: bool) -> Vec<Divergence> {
+        let (left, right) = ([flow(0x100, left)], [flow(0x200, right)]);
+        let matched = TaskMatch { pairs: vec![(0, 0, 1.0)], ..Default::default() };
+        diff_tasks(&left, &right, &matched, traps)
+    }
+
+    /// Class and field of the only divergence of a getpid followed by
+    /// the events.
+    fn classify_one(left: TraceEvent, right: TraceEvent) -> (DivergenceClass, Option<String>) {
+        let getpid = call(SYS_GETPID, &[], 0x100);
+        let divs = diff(vec![getpid.clone(), left], vec![getpid, right], false);
+        assert_eq!(divs.len(), 1, "{:?}", divs);
+        (divs[0].class, divs[0].field.clone())
+    }
+
+    fn field(name: &str) -> Option<String> {
+        Some(name.to_string())
+    }
+
+    #[test]
+    fn same_events_do_not_diverge() {
+        let events = vec![call(SYS_GETPID, &[], 0x100), read("hi")];
+        assert!(diff(events.clone(), events, false).is_empty());
+    }
+
+    #[test]
+    fn classifies_results() {
+        let lseek = |result| call(SYS_LSEEK, &[3, 0, 2], result);
+        assert_eq!(
+            classify_one(lseek(-ENOENT as i64), lseek(-EINVAL as i64)),
+            (DivergenceClass::Errno, field("result"))
+        );
+        assert_eq!(
+            classify_one(lseek(10), lseek(-ENOENT as i64)),
+            (DivergenceClass::SuccessFailure, field("result"))
+        );
+        assert_eq!(classify_one(lseek(10), lseek(12)), (DivergenceClass::ReturnValue, field("result")));
+    }
+
+    #[test]
+    fn classifies_arguments_and_payloads() {
+        let write = |fd| call(SYS_WRITE, &[fd, 0x6000, 3], 3);
+        assert_eq!(classify_one(write(1), write(2)), (DivergenceClass::Argument, field("arg0")));
+        assert_eq!(classify_one(read("hi"), read("ho")), (DivergenceClass::OutPayload, field("arg1")));
+    }
+
+    #[test]
+    fn classifies_missing_and_extra_syscalls() {
+        let (getpid, close) = (call(SYS_GETPID, &[], 0x100), call(SYS_CLOSE, &[3], 0));
+        let divs = diff(vec![getpid.clone(), close.clone()], vec![getpid.clone()], false);
+        assert_eq!(divs.len(), 1);
+        assert_eq!((divs[0].class, divs[0].syscall.as_deref()), (DivergenceClass::MissingSyscall, Some("close")));
+        assert_eq!(divs[0].left.as_ref().and_then(|site| site.index), Some(1));
+        assert!(divs[0].right.as_ref().is_some_and(|site| site.index.is_none()));
+
+        let divs = diff(vec![getpid.clone()], vec![close, getpid], false);
+        assert_eq!(divs.len(), 1);
+        assert_eq!(divs[0].class, DivergenceClass::ExtraSyscall);
+    }
+
+    #[test]
+    fn classifies_signals() {
+        let interrupted = |signum| TraceEvent { signal: SigStage::Exit(signum), ..read("hi") };
+        let entry = TraceEvent { signal: SigStage::Enter(SIGUSR1), ..Default::default() };
+        let divs = diff(vec![entry, interrupted(SIGUSR1)], vec![read("hi")], false);
+        let classes = divs.iter().map(|div| (div.class, div.field.as_deref())).collect::<Vec<_>>();
+        assert_eq!(classes, [(DivergenceClass::Signal, Some("signal")), (DivergenceClass::Signal, Some("signal"))]);
+    }
+
+    #[test]
+    fn classifies_task_structure() {
+        let mut left = [flow(0x100, vec![]), flow(0x101, vec![])];
+        let mut right = [flow(0x200, vec![]), flow(0x201, vec![]), flow(0x202, vec![])];
+        left[1].clone_flags = Some(0x11);
+        right[1].clone_flags = Some(0x10f00);
+        left[0].exit = Some(ExitReason::ExitGroup);
+        right[0].exit = Some(ExitReason::Killed { by: 0x201 });
+        right[2].exit = Some(ExitReason::Exit);
+        let matched = TaskMatch { pairs: vec![(0, 0, 1.0), (1, 1, 1.0)], left_only: vec![], right_only: vec![2] };
+        let divs = diff_tasks(&left, &right, &matched, false);
+        let fields = divs.iter().map(|div| (div.class, div.field.as_deref())).collect::<Vec<_>>();
+        assert_eq!(fields, [
+            (DivergenceClass::TaskStructure, Some("exit")),
+            (DivergenceClass::TaskStructure, Some("clone_flags")),
+            (DivergenceClass::TaskStructure, None),
+        ]);
+        assert_eq!(divs[2].detail, "task only in lkmodel");
+    }
+
+    #[test]
+    fn compares_traps_by_cause_if_asked() {
+        let getpid = call(SYS_GETPID, &[], 0x100);
+        let illegal = trap(ILLEGAL_INSN, 0xffffffff);
+        let (left, right) = (vec![illegal.clone(), getpid.clone()], vec![getpid.clone()]);
+        assert!(diff(left.clone(), right.clone(), false).is_empty());
+        let divs = diff(left, right, true);
+        let classes = divs.iter().map(|div| (div.class, div.field.as_deref())).collect::<Vec<_>>();
+        assert_eq!(classes, [(DivergenceClass::Trap, Some("trap"))]);
+
+        let fault = |addr| vec![trap(LOAD_PAGE_FAULT, addr), getpid.clone()];
+        let divs = diff(fault(0x7000), fault(0x8000), true);
+        assert_eq!(divs.len(), 1);
+        assert_eq!(divs[0].class, DivergenceClass::Trap);
+        assert_eq!(divs[0].left_value.as_deref(), Some("load_page_fault(addr=0x7000)"));
+
+        // Linux turns the illegal instruction into SIGILL, lkmodel does not.
+        let sigill = TraceEvent { signal: SigStage::Exit(SIGILL), ..illegal.clone() };
+        let divs = diff(vec![sigill, getpid.clone()], vec![illegal, getpid], true);
+        let classes = divs.iter().map(|div| div.class).collect::<Vec<_>>();
+        assert_eq!(classes, [DivergenceClass::Signal]);
+    }
+}
@@ -0,0 +1,301 @@
+//! Syscalls which are equivalent to one another.
+//!
+//! libc and the kernel may reach the same effect through different
+//! syscalls: faccessat2 for faccessat, clone3 for clone, and so on. Each
+//! rule rewrites a call into an equivalent call of a canonical syscall,
+//! when the args allow it, so that a diff compares what the calls do rather
+//! than which variant was called. A rewritten call is decoded as usual.
+//! Calls of the same variant are compared as they are, see
+//! [`canonical_pair`].
+
+use std::borrow::Cow;
+use crate::event::{SigStage, TraceEvent, TracePayload};
+use crate::fd::{F_DUPFD, F_DUPFD_CLOEXEC};
+use crate::ipc::O_CLOEXEC;
+use crate::sched::*;
+use crate::stat::AT_EMPTY_PATH;
+use crate::sysno::*;
+use crate::{IN, OUT};
+
+const AT_FDCWD: u64 = -100i64 as u64;
+
+/// A call of `sysno` rewritten as an equivalent one.
+pub struct Rule {
+    pub sysno: u64,
+    /// Variant and canonical call
+    pub desc: &'static str,
+    /// None if not equivalent with these args
+    pub rewrite: fn(&TraceEvent) -> Option<TraceEvent>,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        sysno: SYS_FACCESSAT2,
+        desc: "faccessat2(dirfd, path, mode, 0) = faccessat(dirfd, path, mode)",
+        rewrite: faccessat2,
+    },
+    Rule {
+        sysno: SYS_FSTATAT,
+        desc: "fstatat(fd, \"\", buf, AT_EMPTY_PATH) = fstat(fd, buf)",
+        rewrite: fstatat,
+    },
+    Rule {
+        sysno: SYS_CLONE3,
+        desc: "clone3({flags, exit_signal, stack, ...}) = clone(flags|exit_signal, stack, ...)",
+        rewrite: clone3,
+    },
+    Rule {
+        sysno: SYS_WAITID,
+        desc: "waitid(P_PID, pid, info, WEXITED|options, rusage) = wait4(pid, status, options, rusage) -> info.si_pid",
+        rewrite: waitid,
+    },
+    Rule {
+        sysno: SYS_FCNTL,
+        desc: "fcntl(fd, F_DUPFD, min) -> newfd = dup3(fd, newfd, 0) -> newfd",
+        rewrite: fcntl,
+    },
+];
+
+/// The event rewritten by the rule of its syscall, if any applies.
+pub fn canonical(evt: &TraceEvent) -> Cow<'_, TraceEvent> {
+    if evt.is_trap() || matches!(evt.signal, SigStage::Enter(_)) {
+        return Cow::Borrowed(evt);
+    }
+    RULES.iter()
+        .find(|rule| rule.sysno == evt.head.ax[7])
+        .and_then(|rule| (rule.rewrite)(evt))
+        .map_or(Cow::Borrowed(evt), Cow::Owned)
+}
+
+/// Two events as compared: rewritten only if they are different variants.
+pub fn canonical_pair<'a>(
+    left: &'a TraceEvent, right: &'a TraceEvent
+) -> (Cow<'a, TraceEvent>, Cow<'a, TraceEvent>) {
+    if left.head.ax[7] == right.head.ax[7] {
+        (Cow::Borrowed(left), Cow::Borrowed(right))
+    } else {
+        (canonical(left), canonical(right))
+    }
+}
+
+/// The event as a call of another syscall with the args given.
+fn rewritten(evt: &TraceEvent, sysno: u64, args: &[u64]) -> TraceEvent {
+    let mut evt = evt.clone();
+    evt.head.ax[7] = sysno;
+    evt.head.ax[..args.len()].copy_from_slice(args);
+    evt.head.ax[args.len()..7].fill(0);
+    evt
+}
+
+fn faccessat2(evt: &TraceEvent) -> Option<TraceEvent> {
+    let ax = &evt.head.ax;
+    (ax[3] == 0).then(|| rewritten(evt, SYS_FACCESSAT, &ax[..3]))
+}
+
+fn fstatat(evt: &TraceEvent) -> Option<TraceEvent> {
+    let ax = &evt.head.ax;
+    let path = evt.find_payload_io(IN, 1)?;
+    if ax[0] == AT_FDCWD || ax[3] & AT_EMPTY_PATH == 0 || path.data.first().is_some_and(|&c| c != 0) {
+        return None;
+    }
+    let mut canon = rewritten(evt, SYS_FSTAT, &[ax[0], ax[2]]);
+    canon.payloads.retain(|payload| payload.inout == OUT && payload.index == 2);
+    canon.payloads.iter_mut().for_each(|payload| payload.index = 1);
+    Some(canon)
+}
+
+fn clone3(evt: &TraceEvent) -> Option<TraceEvent> {
+    let cl_args = CloneArgs::from_bytes(&evt.find_payload_io(IN, 0)?.data)?;
+    // Beyond what clone can do
+    if cl_args.set_tid_size != 0 || cl_args.flags & CLONE_INTO_CGROUP != 0 || cl_args.exit_signal & !CSIGNAL != 0 {
+        return None;
+    }
+    // clone stores the pidfd in parent_tid.
+    let parent_tid = if cl_args.flags & CLONE_PIDFD != 0 { cl_args.pidfd } else { cl_args.parent_tid };
+    // clone takes the top of the stack.
+    let stack = if cl_args.stack != 0 { cl_args.stack + cl_args.stack_size } else { 0 };
+    let args = [cl_args.flags | cl_args.exit_signal, stack, parent_tid, cl_args.tls, cl_args.child_tid];
+    let mut canon = rewritten(evt, SYS_CLONE, &args);
+    canon.payloads.clear();
+    Some(canon)
+}
+
+fn waitid(evt: &TraceEvent) -> Option<TraceEvent> {
+    let ax = &evt.head.ax;
+    let pid = match ax[0] {
+        P_ALL => -1,
+        P_PID => ax[1] as i64,
+        P_PGID => -(ax[1] as i64),
+        _ => return None,
+    };
+    // wait4 waits for exited children only and always reaps them.
+    if ax[3] & WEXITED == 0 || ax[3] & WNOWAIT != 0 {
+        return None;
+    }
+    // The status of wait4 is at the address of the siginfo.
+    let mut canon = rewritten(evt, SYS_WAIT4, &[pid as u64, ax[2], ax[3] & !WEXITED, ax[4]]);
+    canon.payloads.clear();
+    if evt.result == 0 {
+        let info = &evt.find_payload_io(OUT, 2)?.data;
+        let child = siginfo_pid(info)?;
+        canon.result = child as i64;
+        // The status is only stored once a child is reported.
+        if child != 0 {
+            let (code, status) = siginfo_status(info)?;
+            let status = wait_status(code, status)?;
+            canon.payloads.push(TracePayload { inout: OUT, index: 1, data: status.to_ne_bytes().to_vec() });
+        }
+    }
+    Some(canon)
+}
+
+/// Only once it succeeds, since dup3 takes the fd which F_DUPFD returns.
+fn fcntl(evt: &TraceEvent) -> Option<TraceEvent> {
+    let ax = &evt.head.ax;
+    if evt.result < 0 || (ax[1] != F_DUPFD && ax[1] != F_DUPFD_CLOEXEC) {
+        return None;
+    }
+    let flags = if ax[1] == F_DUPFD_CLOEXEC { O_CLOEXEC } else { 0 };
+    Some(rewritten(evt, SYS_DUP3, &[ax[0], evt.result as u64, flags]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::EventEq;
+    use crate::event::USER_ECALL;
+    use crate::signal::SIGKILL;
+    use crate::stat::AT_EACCESS;
+
+    fn call(sysno: u64, args: &[u64], result: i64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.cause = USER_ECALL;
+        evt.head.ax[7] = sysno;
+        evt.head.ax[..args.len()].copy_from_slice(args);
+        evt.result = result;
+        evt
+    }
+
+    fn with_payload(mut evt: TraceEvent, inout: u64, index: usize, data: Vec<u8>) -> TraceEvent {
+        evt.payloads.push(TracePayload { inout, index, data });
+        evt
+    }
+
+    fn is_rewritten(evt: &TraceEvent) -> bool {
+        matches!(canonical(evt), Cow::Owned(_))
+    }
+
+    /// siginfo of a child as filled by waitid.
+    fn siginfo(pid: i32, code: i32, status: i32) -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        data[8..12].copy_from_slice(&code.to_ne_bytes());
+        data[16..20].copy_from_slice(&pid.to_ne_bytes());
+        data[24..28].copy_from_slice(&status.to_ne_bytes());
+        data
+    }
+
+    fn wait4(pid: i64, status: i32) -> TraceEvent {
+        let evt = call(SYS_WAIT4, &[-1i64 as u64, 0x7100, 0, 0], pid);
+        with_payload(evt, OUT, 1, status.to_ne_bytes().to_vec())
+    }
+
+    fn waitid(pid: i32, code: i32, status: i32) -> TraceEvent {
+        let evt = call(SYS_WAITID, &[P_ALL, 0, 0x7200, WEXITED, 0], 0);
+        with_payload(evt, OUT, 2, siginfo(pid, code, status))
+    }
+
+    #[test]
+    fn faccessat2_without_flags() {
+        let evt = call(SYS_FACCESSAT2, &[AT_FDCWD, 0x5000, 4, 0], 0);
+        let canon = canonical(&evt);
+        assert_eq!(canon.head.ax[7], SYS_FACCESSAT);
+        assert_eq!(canon.head.ax[..4], [AT_FDCWD, 0x5000, 4, 0]);
+        assert!(!is_rewritten(&call(SYS_FACCESSAT2, &[AT_FDCWD, 0x5000, 4, AT_EACCESS], 0)));
+    }
+
+    #[test]
+    fn fstatat_empty_path() {
+        let evt = call(SYS_FSTATAT, &[3, 0x5000, 0x7000, AT_EMPTY_PATH], 0);
+        let evt = with_payload(evt, IN, 1, vec![0]);
+        let evt = with_payload(evt, OUT, 2, vec![1; 128]);
+        let canon = canonical(&evt);
+        assert_eq!(canon.head.ax[7], SYS_FSTAT);
+        assert_eq!(canon.head.ax[..3], [3, 0x7000, 0]);
+        assert_eq!(canon.payloads.len(), 1);
+        assert_eq!((canon.payloads[0].inout, canon.payloads[0].index), (OUT, 1));
+
+        let named = call(SYS_FSTATAT, &[3, 0x5000, 0x7000, AT_EMPTY_PATH], 0);
+        assert!(!is_rewritten(&with_payload(named, IN, 1, b"x\0".to_vec())));
+    }
+
+    #[test]
+    fn clone3_as_clone() {
+        let cl_args = |set_tid_size: u64| {
+            let fields = [CLONE_VM | CLONE_SETTLS, 0, 0x8000, 0, 17, 0x10000, 0x2000, 0x9000, 0, set_tid_size, 0];
+            fields.iter().flat_map(|field: &u64| field.to_ne_bytes()).collect::<Vec<_>>()
+        };
+        let evt = with_payload(call(SYS_CLONE3, &[0x6000, 88], 0x300), IN, 0, cl_args(0));
+        let canon = canonical(&evt);
+        assert_eq!(canon.head.ax[7], SYS_CLONE);
+        assert_eq!(canon.head.ax[..5], [CLONE_VM | CLONE_SETTLS | 17, 0x12000, 0, 0x9000, 0x8000]);
+        assert_eq!(canon.result, 0x300);
+        assert!(canon.payloads.is_empty());
+
+        let evt = with_payload(call(SYS_CLONE3, &[0x6000, 88], 0x300), IN, 0, cl_args(1));
+        assert!(!is_rewritten(&evt));
+    }
+
+    #[test]
+    fn waitid_keeps_exit_status() {
+        let evt = waitid(0x300, CLD_EXITED, 3);
+        let canon = canonical(&evt);
+        assert_eq!(canon.head.ax[7], SYS_WAIT4);
+        assert_eq!(canon.head.ax[..4], [-1i64 as u64, 0x7200, 0, 0]);
+        assert_eq!(canon.result, 0x300);
+        assert_eq!(canon.payloads[0].data, (3i32 << 8).to_ne_bytes());
+
+        let eq = EventEq::default();
+        assert_eq!(eq.key(&waitid(0x300, CLD_EXITED, 3)), eq.key(&wait4(0x300, 3 << 8)));
+        assert_ne!(eq.key(&waitid(0x300, CLD_EXITED, 0)), eq.key(&wait4(0x300, 3 << 8)));
+        assert_eq!(eq.key(&waitid(0x300, CLD_KILLED, SIGKILL as i32)), eq.key(&wait4(0x300, SIGKILL as i32)));
+        assert_ne!(eq.key(&waitid(0x300, CLD_KILLED, SIGKILL as i32)), eq.key(&wait4(0x300, 0)));
+    }
+
+    #[test]
+    fn waitid_without_child() {
+        // WNOHANG: no status is stored.
+        let evt = call(SYS_WAITID, &[P_ALL, 0, 0x7200, WEXITED | WNOHANG, 0], 0);
+        let evt = with_payload(evt, OUT, 2, siginfo(0, 0, 0));
+        let canon = canonical(&evt);
+        assert_eq!(canon.head.ax[..3], [-1i64 as u64, 0x7200, WNOHANG]);
+        assert_eq!(canon.result, 0);
+        assert!(canon.payloads.is_empty());
+
+        let nowait = call(SYS_WAITID, &[P_PID, 0x300, 0x7200, WEXITED | WNOWAIT, 0], 0);
+        assert!(!is_rewritten(&with_payload(nowait, OUT, 2, siginfo(0x300, CLD_EXITED, 0))));
+    }
+
+    #[test]
+    fn fcntl_dupfd() {
+        let evt = call(SYS_FCNTL, &[3, F_DUPFD_CLOEXEC, 10], 10);
+        let canon = canonical(&evt);
+        assert_eq!(canon.head.ax[7], SYS_DUP3);
+        assert_eq!(canon.head.ax[..3], [3, 10, O_CLOEXEC]);
+        assert!(!is_rewritten(&call(SYS_FCNTL, &[3, F_DUPFD, 10], -24)));
+    }
+
+    #[test]
+    fn same_variant_is_not_rewritten() {
+        let (left, right) = (wait4(0x300, 0), wait4(0x301, 0));
+        let pair = canonical_pair(&left, &right);
+        assert!(matches!(pair, (Cow::Borrowed(_), Cow::Borrowed(_))));
+        let (left, right) = (waitid(0x300, CLD_EXITED, 0), waitid(0x300, CLD_EXITED, 1));
+        let pair = canonical_pair(&left, &right);
+        assert!(matches!(pair, (Cow::Borrowed(_), Cow::Borrowed(_))));
+
+        let (left, right) = (wait4(0x300, 0), waitid(0x300, CLD_EXITED, 0));
+        let (left, right) = canonical_pair(&left, &right);
+        assert!(matches!(left, Cow::Borrowed(_)));
+        assert_eq!(right.head.ax[7], SYS_WAIT4);
+    }
+}
@@ -4,8 +4,14 @@
 pub const EPERM:  i32 = 1;
 /// No such file or directory
 pub const ENOENT: i32 = 2;
+/// Interrupted system call
+pub const EINTR:  i32 = 4;
+/// Bad file number
+pub const EBADF:  i32 = 9;
 /// No child processes
 pub const ECHILD: i32 = 10;
+/// Try again
+pub const EAGAIN: i32 = 11;
 /// Not a directory
 pub const ENOTDIR:i32 = 20;
 /// Is a directory
@@ -14,6 +20,32 @@ pub const EISDIR: i32 = 21;
 pub const EINVAL: i32 = 22;
 /// Not a typewriter
 pub const ENOTTY: i32 = 25;
+/// Broken pipe
+pub const EPIPE:  i32 = 32;
+/// Function not implemented
+pub const ENOSYS: i32 = 38;
+/// Socket operation on non-socket
+pub const ENOTSOCK: i32 = 88;
+/// Protocol not available
+pub const ENOPROTOOPT: i32 = 92;
+/// Address family not supported by protocol
+pub const EAFNOSUPPORT: i32 = 97;
+/// Address already in use
+pub const EADDRINUSE: i32 = 98;
+/// Cannot assign requested address
+pub const EADDRNOTAVAIL: i32 = 99;
+/// Connection reset by peer
+pub const ECONNRESET: i32 = 104;
+/// Transport endpoint is not connected
+pub const ENOTCONN: i32 = 107;
+/// Connection timed out
+pub const ETIMEDOUT: i32 = 110;
+/// Connection refused
+pub const ECONNREFUSED: i32 = 111;
+/// Operation now in progress
+pub const EINPROGRESS: i32 = 115;
+
+pub const UNKNOWN_ERRNO: &str = "Unknown errno";
 
 pub fn errno_name(err: i64) -> &'static str {
     let err = err as i32;
@@ -21,14 +53,26 @@ pub fn errno_name(err: i64) -> &'static str {
         0 => "OK",
         EPERM => "EPERM",
         ENOENT => "ENOENT",
+        EINTR => "EINTR",
+        EBADF => "EBADF",
         ECHILD => "ECHILD",
+        EAGAIN => "EAGAIN",
         ENOTDIR => "ENOTDIR",
         EISDIR => "EISDIR",
         EINVAL => "EINVAL",
         ENOTTY => "ENOTTY",
-        _ => {
-            println!("Unknown errno: {}", -err);
-            "Unknown errno"
-        },
+        EPIPE => "EPIPE",
+        ENOSYS => "ENOSYS",
+        ENOTSOCK => "ENOTSOCK",
+        ENOPROTOOPT => "ENOPROTOOPT",
+        EAFNOSUPPORT => "EAFNOSUPPORT",
+        EADDRINUSE => "EADDRINUSE",
+        EADDRNOTAVAIL => "EADDRNOTAVAIL",
+        ECONNRESET => "ECONNRESET",
+        ENOTCONN => "ENOTCONN",
+        ETIMEDOUT => "ETIMEDOUT",
+        ECONNREFUSED => "ECONNREFUSED",
+        EINPROGRESS => "EINPROGRESS",
+        _ => UNKNOWN_ERRNO,
     }
 }
@@ -3,19 +3,40 @@
 use std::fs::File;
 use std::io::BufReader;
 use crate::errno::errno_name;
+use crate::decode::{decode_event, Arch, ArgValue};
+use crate::stat::{kstat_name, statx_name, statx_mask_name, statfs_name, at_flag_name, access_flag_name, utimens_name};
+pub use crate::stat::KStat;
+pub use crate::flow::TraceFlow;
+use crate::futex::*;
+use crate::ipc::{
+    pipe_flag_name, eventfd_flag_name, memfd_flag_name, oflag_name, ipc_key_name,
+    ipc_get_flag_name, ipc_cmd_name, shmat_flag_name, ipc_msg_flag_name, sembufs_name,
+    msgbuf_name, mq_attr_name, O_CREAT,
+};
+use crate::poll::{
+    parse_pollfds, pollfds_name, pollfds_revents_name, fd_set_name, epoll_ctl_op_name,
+    epoll_create_flag_name, epoll_event_struct_name, epoll_events_name,
+};
 use crate::mmap::{map_name, prot_name};
+use crate::sched::{
+    cld_code_name, idtype_name, siginfo_pid, siginfo_status, wait_status_name, CloneArgs, CLD_EXITED, P_PID,
+};
 use crate::sysno::*;
-use crate::signal::{SigAction, sig_name};
+use crate::trap::trap_name;
+use crate::signal::{parse_sigset, sig_name, sigset_name, SigAction};
+use crate::socket::{
+    domain_name, sock_type_name, protocol_name, accept_flag_name, sockaddr_name,
+    sockopt_level_name, sockopt_name, sockopt_value, shutdown_how_name, msg_flag_name,
+};
 use std::ffi::CStr;
 use std::fmt::{Display, Formatter};
 use std::mem;
 use crate::signal::{SIG_BLOCK, SIG_UNBLOCK, SIG_SETMASK};
 use crate::payload::parse_payloads;
 use std::io::prelude::*;
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
 use std::collections::HashMap;
-use std::sync::Mutex;
-use once_cell::unsync::Lazy;
+use std::sync::{Arc, RwLock};
 
 pub const LK_MAGIC: u16 = 0xABCD;
 pub const TE_SIZE: usize = mem::size_of::<TraceHead>();
@@ -24,7 +45,30 @@ pub const USER_ECALL: u64 = 8;
 
 const AT_FDCWD: u64 = -100i64 as u64;
 
-static TID_MAP: Mutex<Lazy<HashMap<i64, String>>> = Mutex::new(Lazy::new(|| HashMap::new()));
+/// Names of the tids of one trace for level 2, `tid_<n>` by order of first
+/// appearance in the trace. These are the tids the tasks see, as returned
+/// by clone, gettid and set_tid_address, not the trace tids in sscratch.
+/// Shared by the events read from the trace.
+#[derive(Clone, Debug, Default)]
+pub struct TidMap(Arc<RwLock<HashMap<i64, String>>>);
+
+impl TidMap {
+    fn add(&self, tid: i64) {
+        let mut names = self.0.write().unwrap();
+        let next = names.len();
+        names.entry(tid).or_insert_with(|| format!("tid_{}", next));
+    }
+
+    /// Name of a tid; tids from outside the trace are all alike, and other
+    /// values such as -1 are kept.
+    pub fn name(&self, tid: i64) -> String {
+        match self.0.read().unwrap().get(&tid) {
+            Some(name) => name.clone(),
+            None if tid > 0 => "tid_?".to_string(),
+            None => tid.to_string(),
+        }
+    }
+}
 
 #[derive(Clone, Debug, Default)]
 #[repr(C)]
@@ -55,7 +99,7 @@ pub struct TracePayload {
     pub data: Vec<u8>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub enum SigStage {
     #[default]
     Empty,
@@ -66,25 +110,19 @@ pub enum SigStage {
 #[derive(Clone, Debug, Default)]
 pub struct TraceEvent {
     pub head: TraceHead,
+    /// Head of the OUT record, once the syscall has returned.
+    pub out_head: Option<TraceHead>,
     pub result: i64,
     pub payloads: Vec<TracePayload>,
     pub signal: SigStage,
     pub raw_fmt: bool,
     pub level: usize,
-}
-
-pub struct TraceFlow {
-    pub events: Vec<TraceEvent>,
-    pub signal_stack: Vec<TraceEvent>,
-}
-
-impl TraceFlow {
-    pub fn new() -> Self {
-        Self {
-            events: Vec::new(),
-            signal_stack: Vec::new(),
-        }
-    }
+    /// Index of the record in the trace.
+    pub seq: usize,
+    /// File offset of the record.
+    pub offset: u64,
+    /// Tid names of the trace
+    pub tids: TidMap,
 }
 
 const UTS_LEN: usize = 64;
@@ -95,89 +133,122 @@ struct UTSName {
 }
 const UTSNAME_SIZE: usize = mem::size_of::<UTSName>();
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct KStat {
-    st_dev: u64,
-    st_ino: u64,
-    st_mode: u32,
-    st_nlink: u32,
-    st_uid: u32,
-    st_gid: u32,
-    st_rdev: u64,
-    _pad0: u64,
-    st_size: u64,
-    st_blksize: u32,
-    _pad1: u32,
-    st_blocks: u64,
-    st_atime_sec: isize,
-    st_atime_nsec: isize,
-    st_mtime_sec: isize,
-    st_mtime_nsec: isize,
-    st_ctime_sec: isize,
-    st_ctime_nsec: isize,
-}
-const KSTAT_SIZE: usize = mem::size_of::<KStat>();
+
+/// Decodes one syscall: fills typed `args` in place, returns (name, argc, result).
+pub(crate) type DecodeFn = fn(&TraceEvent, &mut [ArgValue]) -> (&'static str, usize, String);
+
+/// Built-in decoders of Linux syscalls, registered into the default
+/// [`DecoderRegistry`](crate::decode::DecoderRegistry).
+pub(crate) const SYSCALL_DECODERS: &[(u64, DecodeFn)] = &[
+    (SYS_IOCTL, |evt, _| evt.do_common("ioctl", 3)),
+    (SYS_FCNTL, |evt, _| evt.do_common("fcntl", 3)),
+    (SYS_DUP3, |evt, _| evt.do_common("dup3", 3)),
+    (SYS_FACCESSAT, TraceEvent::do_faccessat),
+    (SYS_FACCESSAT2, TraceEvent::do_faccessat2),
+    (SYS_MKDIRAT, |evt, _| evt.do_common("mkdirat", 3)),
+    (SYS_GETCWD, TraceEvent::do_getcwd),
+    (SYS_CHDIR, TraceEvent::do_chdir),
+    (SYS_FCHMODAT, |evt, _| evt.do_common("fchmodat", 4)),
+    (SYS_FCHOWNAT, |evt, _| evt.do_common("fchownat", 5)),
+    (SYS_OPENAT, TraceEvent::do_openat),
+    (SYS_CLOSE, |evt, _| evt.do_common("close", 1)),
+    (SYS_LSEEK, |evt, _| evt.do_common("lseek", 3)),
+    (SYS_SENDFILE, |evt, _| evt.do_common("sendfile", 4)),
+    (SYS_READ, TraceEvent::do_read),
+    (SYS_WRITE, TraceEvent::do_write),
+    (SYS_WRITEV, |evt, _| evt.do_common("writev", 3)),
+    (SYS_UNLINKAT, TraceEvent::do_unlinkat),
+    (SYS_FSTATAT, TraceEvent::do_fstatat),
+    (SYS_FSTAT, TraceEvent::do_fstat),
+    (SYS_STATX, TraceEvent::do_statx),
+    (SYS_STATFS, |evt, args| evt.do_statfs("statfs", args)),
+    (SYS_FSTATFS, |evt, args| evt.do_statfs("fstatfs", args)),
+    (SYS_UTIMENSAT, TraceEvent::do_utimensat),
+    (SYS_EXIT, |evt, _| evt.do_common("exit", 1)),
+    (SYS_EXIT_GROUP, |evt, _| evt.do_common("exit_group", 1)),
+    (SYS_SET_TID_ADDRESS, TraceEvent::do_set_tid_address),
+    (SYS_SET_ROBUST_LIST, |evt, _| evt.do_common("set_robust_list", 2)),
+    (SYS_CLOCK_GETTIME, |evt, _| evt.do_common("clock_gettime", 2)),
+    (SYS_UNAME, TraceEvent::do_uname),
+    (SYS_BRK, TraceEvent::do_brk),
+    (SYS_MOUNT, |evt, _| evt.do_common("mount", 5)),
+    (SYS_MSYNC, |evt, _| evt.do_common("msync", 3)),
+    (SYS_MMAP, TraceEvent::do_mmap),
+    (SYS_MUNMAP, |evt, _| evt.do_common("munmap", 2)),
+    (SYS_MPROTECT, TraceEvent::do_mprotect),
+    (SYS_PRLIMIT64, |evt, _| evt.do_common("prlimit64", 4)),
+    (SYS_GETRANDOM, |evt, _| evt.do_common("getrandom", 3)),
+    (SYS_KILL, TraceEvent::do_kill),
+    (SYS_RT_SIGACTION, TraceEvent::do_rt_sigaction),
+    (SYS_RT_SIGPROCMASK, TraceEvent::do_rt_sigprocmask),
+    (SYS_CLONE, TraceEvent::do_clone),
+    (SYS_CLONE3, TraceEvent::do_clone3),
+    (SYS_EXECVE, TraceEvent::do_execve),
+    (SYS_GETTID, |evt, _| evt.do_common("gettid", 0)),
+    (SYS_GETGID, |evt, _| evt.do_common("getgid", 0)),
+    (SYS_GETEGID, |evt, _| evt.do_common("getegid", 0)),
+    (SYS_GETPID, TraceEvent::do_getpid),
+    (SYS_GETPPID, TraceEvent::do_getppid),
+    (SYS_GETUID, |evt, _| evt.do_common("getuid", 0)),
+    (SYS_GETEUID, |evt, _| evt.do_common("geteuid", 0)),
+    (SYS_TGKILL, |evt, _| evt.do_common("tgkill", 3)),
+    (SYS_WAIT4, TraceEvent::do_wait4),
+    (SYS_WAITID, TraceEvent::do_waitid),
+    (SYS_GETDENTS64, |evt, _| evt.do_common("getdents64", 3)),
+    (SYS_SOCKET, TraceEvent::do_socket),
+    (SYS_SOCKETPAIR, TraceEvent::do_socketpair),
+    (SYS_BIND, |evt, args| evt.do_sockaddr_in("bind", args)),
+    (SYS_CONNECT, |evt, args| evt.do_sockaddr_in("connect", args)),
+    (SYS_LISTEN, |evt, _| evt.do_common("listen", 2)),
+    (SYS_ACCEPT, |evt, args| evt.do_accept("accept", args)),
+    (SYS_ACCEPT4, |evt, args| evt.do_accept("accept4", args)),
+    (SYS_GETSOCKNAME, |evt, args| evt.do_sockaddr_out("getsockname", args)),
+    (SYS_GETPEERNAME, |evt, args| evt.do_sockaddr_out("getpeername", args)),
+    (SYS_SENDTO, TraceEvent::do_sendto),
+    (SYS_RECVFROM, TraceEvent::do_recvfrom),
+    (SYS_SETSOCKOPT, |evt, args| evt.do_sockopt("setsockopt", args)),
+    (SYS_GETSOCKOPT, |evt, args| evt.do_sockopt("getsockopt", args)),
+    (SYS_SHUTDOWN, TraceEvent::do_shutdown),
+    (SYS_FUTEX, TraceEvent::do_futex),
+    (SYS_PPOLL, TraceEvent::do_ppoll),
+    (SYS_PSELECT6, TraceEvent::do_pselect6),
+    (SYS_EPOLL_CREATE1, TraceEvent::do_epoll_create1),
+    (SYS_EPOLL_CTL, TraceEvent::do_epoll_ctl),
+    (SYS_EPOLL_PWAIT, TraceEvent::do_epoll_pwait),
+    (SYS_PIPE2, TraceEvent::do_pipe2),
+    (SYS_EVENTFD2, TraceEvent::do_eventfd2),
+    (SYS_MEMFD_CREATE, TraceEvent::do_memfd_create),
+    (SYS_SHMGET, |evt, args| evt.do_ipc_get("shmget", args)),
+    (SYS_SHMAT, TraceEvent::do_shmat),
+    (SYS_SHMDT, |evt, _| evt.do_common("shmdt", 1)),
+    (SYS_SHMCTL, |evt, args| evt.do_ipc_ctl("shmctl", 1, 3, args)),
+    (SYS_SEMGET, |evt, args| evt.do_ipc_get("semget", args)),
+    (SYS_SEMOP, |evt, args| evt.do_semop("semop", args)),
+    (SYS_SEMTIMEDOP, |evt, args| evt.do_semop("semtimedop", args)),
+    (SYS_SEMCTL, |evt, args| evt.do_ipc_ctl("semctl", 2, 4, args)),
+    (SYS_MSGGET, |evt, args| evt.do_ipc_get("msgget", args)),
+    (SYS_MSGSND, TraceEvent::do_msgsnd),
+    (SYS_MSGRCV, TraceEvent::do_msgrcv),
+    (SYS_MSGCTL, |evt, args| evt.do_ipc_ctl("msgctl", 1, 3, args)),
+    (SYS_MQ_OPEN, TraceEvent::do_mq_open),
+    (SYS_MQ_UNLINK, TraceEvent::do_mq_unlink),
+    (SYS_MQ_TIMEDSEND, TraceEvent::do_mq_timedsend),
+    (SYS_MQ_TIMEDRECEIVE, TraceEvent::do_mq_timedreceive),
+    (SYS_MQ_NOTIFY, |evt, _| evt.do_common("mq_notify", 2)),
+    (SYS_MQ_GETSETATTR, TraceEvent::do_mq_getsetattr),
+];
 
 impl TraceEvent {
-    pub fn handle_syscall(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
-        match self.head.ax[7] {
-            SYS_IOCTL => self.do_common("ioctl", 3),
-            SYS_FCNTL => self.do_common("fcntl", 3),
-            SYS_DUP3 => self.do_common("dup3", 3),
-            SYS_FACCESSAT => self.do_faccessat(args),
-            SYS_MKDIRAT => self.do_common("mkdirat", 3),
-            SYS_GETCWD => self.do_getcwd(args),
-            SYS_CHDIR => self.do_chdir(args),
-            SYS_FCHMODAT => self.do_common("fchmodat", 4),
-            SYS_FCHOWNAT => self.do_common("fchownat", 5),
-            SYS_OPENAT => self.do_openat(args),
-            SYS_CLOSE => self.do_common("close", 1),
-            SYS_LSEEK => self.do_common("lseek", 3),
-            SYS_SENDFILE => self.do_common("sendfile", 4),
-            SYS_READ => self.do_read(args),
-            SYS_WRITE => self.do_write(args),
-            SYS_WRITEV => self.do_common("writev", 3),
-            SYS_UNLINKAT => self.do_unlinkat(args),
-            SYS_FSTATAT => self.do_fstatat(args),
-            SYS_EXIT_GROUP => self.do_common("exit_group", 1),
-            SYS_SET_TID_ADDRESS => self.do_set_tid_address(args),
-            SYS_SET_ROBUST_LIST => self.do_common("set_robust_list", 2),
-            SYS_CLOCK_GETTIME => self.do_common("clock_gettime", 2),
-            SYS_UNAME => self.do_uname(args),
-            SYS_BRK => self.do_brk(args),
-            SYS_MOUNT => self.do_common("mount", 5),
-            SYS_MSYNC => self.do_common("msync", 3),
-            SYS_MMAP => self.do_mmap(args),
-            SYS_MUNMAP => self.do_common("munmap", 2),
-            SYS_MPROTECT => self.do_mprotect(args),
-
-            SYS_PRLIMIT64 => self.do_common("prlimit64", 4),
-            SYS_GETRANDOM => self.do_common("getrandom", 3),
-            SYS_KILL=> self.do_kill(args),
-            SYS_RT_SIGACTION => self.do_rt_sigaction(args),
-            SYS_RT_SIGPROCMASK => self.do_rt_sigprocmask(args),
-            SYS_CLONE => self.do_clone(args),
-            SYS_EXECVE => self.do_execve(args),
-            SYS_GETTID => self.do_common("gettid", 0),
-            SYS_GETGID => self.do_common("getgid", 0),
-            SYS_GETEGID => self.do_common("getegid", 0),
-            SYS_GETPID => self.do_getpid(args),
-            SYS_GETPPID => self.do_getppid(args),
-            SYS_GETUID => self.do_common("getuid", 0),
-            SYS_GETEUID => self.do_common("geteuid", 0),
-            SYS_TGKILL => self.do_common("tgkill", 3),
-            SYS_WAIT4 => self.do_wait4(args),
-            SYS_GETDENTS64 => self.do_common("getdents64", 3),
-            _ => ("", 7, format!("{:#x}", self.result)),
-        }
-    }
-
-    fn do_brk(&self, _args: &mut Vec<String>) -> (&'static str, usize, String) {
+    /// lk_trace is only recorded on riscv64 by now.
+    pub fn arch(&self) -> Arch {
+        Arch::Riscv64
+    }
+
+    fn do_brk(&self, _args: &mut [ArgValue]) -> (&'static str, usize, String) {
         ("brk", 1, format!("{:#x}", self.result))
     }
 
-    fn do_set_tid_address(&self, _args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_set_tid_address(&self, _args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let result = if self.level == 2 {
             self.mask_tid(self.result)
         } else {
@@ -186,14 +257,14 @@ impl TraceEvent {
         ("set_tid_address", 1, result)
     }
 
-    fn do_kill(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_kill(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if self.level == 2 {
-            args[0] = self.mask_tid(parse_usize(&args[0]) as i64);
+            args[0] = ArgValue::Symbol(self.mask_tid(self.head.ax[0] as i64));
         }
         ("kill", 2, format!("{:#x}", self.result))
     }
 
-    fn do_getpid(&self, _args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_getpid(&self, _args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let result = if self.level == 2 {
             self.mask_tid(self.result)
         } else {
@@ -202,7 +273,7 @@ impl TraceEvent {
         ("getpid", 0, result)
     }
 
-    fn do_getppid(&self, _args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_getppid(&self, _args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let result = if self.level == 2 {
             self.mask_tid(self.result)
         } else {
@@ -211,17 +282,54 @@ impl TraceEvent {
         ("getppid", 0, result)
     }
 
-    fn do_wait4(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_wait4(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let result = if self.level == 2 {
-            args[0] = self.mask_tid(parse_usize(&args[0]) as i64);
+            args[0] = ArgValue::Symbol(self.mask_tid(self.head.ax[0] as i64));
             self.mask_tid(self.result)
         } else {
             format!("{:#x}", self.result)
         };
+        // The status is only stored once a child is reported.
+        if self.result > 0 {
+            if let Some(status) = self.find_payload_io(crate::OUT, 1).and_then(|payload| payload.data.get(..4)) {
+                args[1] = ArgValue::Struct(wait_status_name(i32::from_ne_bytes(status.try_into().unwrap())));
+            }
+        }
         ("wait4", 4, result)
     }
 
-    fn do_clone(&self, _args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_waitid(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Symbol(idtype_name(self.head.ax[0]));
+        if self.level == 2 && self.head.ax[0] == P_PID {
+            args[1] = ArgValue::Symbol(self.mask_tid(self.head.ax[1] as i64));
+        }
+        if self.result == 0 {
+            let info = self.find_payload_io(crate::OUT, 2).map(|payload| payload.data.as_slice());
+            if let Some(pid) = info.and_then(siginfo_pid) {
+                let name = if self.level == 2 && pid != 0 {
+                    self.mask_tid(pid as i64)
+                } else {
+                    pid.to_string()
+                };
+                // No child to report with WNOHANG
+                args[2] = match info.and_then(siginfo_status) {
+                    Some((code, status)) if pid != 0 => {
+                        let status = match code {
+                            CLD_EXITED => status.to_string(),
+                            _ => sig_name(status as u64),
+                        };
+                        ArgValue::Struct(format!(
+                            "{{si_pid={}, si_code={}, si_status={}}}", name, cld_code_name(code), status
+                        ))
+                    },
+                    _ => ArgValue::Struct(format!("{{si_pid={}}}", name)),
+                };
+            }
+        }
+        self.do_common("waitid", 5)
+    }
+
+    fn do_clone(&self, _args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let result = if self.result != 0 && self.level == 2 {
             self.mask_tid(self.result)
         } else {
@@ -230,28 +338,35 @@ impl TraceEvent {
         ("clone", 5, result)
     }
 
-    fn mask_tid(&self, oid: i64) -> String {
-        let mut tid_map = TID_MAP.lock().unwrap();
-        if let Some(tid) = tid_map.get(&oid) {
-            tid.clone()
-        } else {
-            let tid = format!("tid_{}", tid_map.len());
-            tid_map.insert(oid, tid.clone());
-            tid
+    fn do_clone3(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if let Some(cl_args) = self.find_payload_io(crate::IN, 0).and_then(|payload| CloneArgs::from_bytes(&payload.data)) {
+            args[0] = ArgValue::Struct(cl_args.to_string());
         }
+        let result = if self.result > 0 && self.level == 2 {
+            self.mask_tid(self.result)
+        } else if self.result < 0 {
+            errno_name(self.result).to_string()
+        } else {
+            format!("{:#x}", self.result)
+        };
+        ("clone3", 2, result)
+    }
+
+    fn mask_tid(&self, oid: i64) -> String {
+        self.tids.name(oid)
     }
 
     #[inline]
     fn do_common(&self, name: &'static str, argc: usize) -> (&'static str, usize, String) {
         if self.result <= 0 {
-            (name, argc, format!("{}", errno_name(self.result)))
+            (name, argc, errno_name(self.result).to_string())
         } else {
             (name, argc, format!("{:#x}", self.result))
         }
     }
 
-    fn do_path(&self, args: &mut Vec<String>, index: usize) {
-        assert!(self.payloads.len() >= 1);
+    fn do_path(&self, args: &mut [ArgValue], index: usize) {
+        assert!(!self.payloads.is_empty());
         let payload = &self.payloads.first().unwrap();
         //assert_eq!(payload.inout, crate::IN);
         assert_eq!(payload.index, index);
@@ -262,30 +377,30 @@ impl TraceEvent {
             }
             Err(_) => "[!parse_str_err!]".to_string(),
         };
-        args[payload.index] = fname;
+        args[payload.index] = ArgValue::Str(fname);
     }
 
-    fn do_openat(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_openat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if self.head.ax[0] == AT_FDCWD {
-            args[0] = "AT_FDCWD".to_string();
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
         }
         self.do_path(args, 1);
         self.do_common("openat", 4)
     }
 
-    fn do_getcwd(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_getcwd(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         self.do_path(args, 0);
         self.do_common("getcwd", 2)
     }
 
-    fn do_chdir(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_chdir(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         self.do_path(args, 0);
         self.do_common("chdir", 1)
     }
 
-    fn do_faccessat(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_faccessat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if self.head.ax[0] == AT_FDCWD {
-            args[0] = "AT_FDCWD".to_string();
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
         }
         self.do_path(args, 1);
         // For faccessat, there're 3 args, NO 'flags'.
@@ -293,62 +408,95 @@ impl TraceEvent {
         self.do_common("faccessat", 3)
     }
 
-    fn do_unlinkat(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_faccessat2(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if self.head.ax[0] == AT_FDCWD {
-            args[0] = "AT_FDCWD".to_string();
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
+        }
+        self.do_path(args, 1);
+        args[3] = ArgValue::Symbol(access_flag_name(self.head.ax[3]));
+        self.do_common("faccessat2", 4)
+    }
+
+    fn do_unlinkat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.head.ax[0] == AT_FDCWD {
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
         }
         self.do_path(args, 1);
         self.do_common("unlinkat", 3)
     }
 
-    fn do_fstatat(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_fstatat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if self.head.ax[0] == AT_FDCWD {
-            args[0] = "AT_FDCWD".to_string();
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
         }
         self.do_path(args, 1);
         if self.result == 0 {
             assert_eq!(self.payloads.len(), 2);
             for payload in &self.payloads {
                 if payload.index == 2 {
-                    args[payload.index] = self.handle_stat(payload);
+                    args[payload.index] = ArgValue::Struct(kstat_name(&payload.data, self.level));
                 }
             }
         }
         self.do_common("fstatat", 4)
     }
 
-    fn handle_stat(&self, payload: &TracePayload) -> String {
-        assert_eq!(payload.inout, crate::OUT);
-        assert_eq!(payload.index, 2);
-        let mut buf = [0u8; KSTAT_SIZE];
-        buf.clone_from_slice(&payload.data[..KSTAT_SIZE]);
-
-        let k = unsafe { mem::transmute::<[u8; KSTAT_SIZE], KStat>(buf) };
-        if self.level != 2 {
-            format!(
-                "{{dev={:#x}, ino={}, mode={:#o}, nlink={}, rdev={}, size={}, blksize={}, blocks={}}}",
-                k.st_dev,
-                k.st_ino,
-                k.st_mode,
-                k.st_nlink,
-                k.st_rdev,
-                k.st_size,
-                k.st_blksize,
-                k.st_blocks
-            )
+    fn do_fstat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result == 0 {
+            if let Some(payload) = self.find_payload_io(crate::OUT, 1) {
+                args[1] = ArgValue::Struct(kstat_name(&payload.data, self.level));
+            }
+        }
+        self.do_common("fstat", 2)
+    }
+
+    fn do_statx(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.head.ax[0] == AT_FDCWD {
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
+        }
+        self.do_str(args, 1);
+        args[2] = ArgValue::Symbol(at_flag_name(self.head.ax[2]));
+        args[3] = ArgValue::Symbol(statx_mask_name(self.head.ax[3] as u32));
+        if self.result == 0 {
+            if let Some(payload) = self.find_payload_io(crate::OUT, 4) {
+                args[4] = ArgValue::Struct(statx_name(&payload.data, self.level));
+            }
+        }
+        self.do_common("statx", 5)
+    }
+
+    /// statfs(path, buf) and fstatfs(fd, buf)
+    fn do_statfs(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.head.ax[7] == SYS_STATFS {
+            self.do_str(args, 0);
+        }
+        if self.result == 0 {
+            if let Some(payload) = self.find_payload_io(crate::OUT, 1) {
+                args[1] = ArgValue::Struct(statfs_name(&payload.data, self.level));
+            }
+        }
+        self.do_common(name, 2)
+    }
+
+    fn do_utimensat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.head.ax[0] == AT_FDCWD {
+            args[0] = ArgValue::Symbol("AT_FDCWD".to_string());
+        }
+        if self.head.ax[1] == 0 {
+            args[1] = ArgValue::Null;
         } else {
-            format!(
-                "{{dev, ino, mode={:#o}, nlink={}, rdev={}, size={}, blksize, blocks={}}}",
-                k.st_mode,
-                k.st_nlink,
-                k.st_rdev,
-                k.st_size,
-                k.st_blocks
-            )
+            self.do_str(args, 1);
         }
+        if self.head.ax[2] == 0 {
+            args[2] = ArgValue::Null;
+        } else if let Some(payload) = self.find_payload_io(crate::IN, 2) {
+            args[2] = ArgValue::Struct(utimens_name(&payload.data, self.level));
+        }
+        args[3] = ArgValue::Symbol(at_flag_name(self.head.ax[3]));
+        self.do_common("utimensat", 4)
     }
 
-    fn do_uname(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_uname(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         assert_eq!(self.payloads.len(), 1);
         let payload = &self.payloads.first().unwrap();
         assert_eq!(payload.inout, crate::OUT);
@@ -368,19 +516,19 @@ impl TraceEvent {
             names.push(format!("{:?}", fname));
         }
         let r_uname = names.join(", ");
-        args[payload.index] = format!("{{{}}}", r_uname);
+        args[payload.index] = ArgValue::Struct(format!("{{{}}}", r_uname));
         ("uname", 1, format!("{:#x}", self.result))
     }
 
-    fn do_mmap(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_mmap(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if !self.raw_fmt {
             if self.head.ax[0] == 0 {
-                args[0] = String::from("NULL");
+                args[0] = ArgValue::Null;
             }
-            args[2] = prot_name(self.head.ax[2]);
-            args[3] = map_name(self.head.ax[3]);
+            args[2] = ArgValue::Symbol(prot_name(self.head.ax[2]));
+            args[3] = ArgValue::Symbol(map_name(self.head.ax[3]));
             if self.head.ax[4] == u64::MAX {
-                args[4] = "-1".to_string();
+                args[4] = ArgValue::Int(-1);
             }
         }
         if self.result <= 0 {
@@ -390,111 +538,486 @@ impl TraceEvent {
         }
     }
 
-    fn do_rt_sigaction(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_rt_sigaction(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let signum = self.head.ax[0];
-        args[0] = sig_name(signum);
+        args[0] = ArgValue::Symbol(sig_name(signum));
 
-        if let Some((sig_action, index)) = parse_sigaction(self) {
-            args[index] = sig_action.to_string();
+        // new action on IN, old action on OUT
+        for (inout, index) in [(crate::IN, 1), (crate::OUT, 2)] {
+            if self.head.ax[index] == 0 {
+                args[index] = ArgValue::Null;
+            } else if let Some(sig_action) = parse_sigaction(self, inout) {
+                args[index] = ArgValue::Struct(sig_action.to_string());
+            }
         }
         ("rt_sigaction", 3, format!("{:#x}", self.result))
     }
 
-    fn do_rt_sigprocmask(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_rt_sigprocmask(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         args[0] = match self.head.ax[0] {
-            SIG_BLOCK => "SIG_BLOCK",
-            SIG_UNBLOCK => "SIG_UNBLOCK",
-            SIG_SETMASK => "SIG_SETMASK",
-            _ => panic!("bad how"),
-        }.to_string();
-
-        let mut index = 0;
-        if self.head.ax[1] != 0 {
-            let payload = self.payloads.get(index).unwrap();
-            index += 1;
-            let mut buf = [0u8; 8];
-            buf.clone_from_slice(&payload.data[..8]);
-            let nset = unsafe { mem::transmute::<[u8; 8], u64>(buf) };
-            args[1] = format!("nset: {:#x}", nset);
-        } else {
-            args[1] = format!("nset: NULL");
-        }
-        if self.head.ax[2] != 0 {
-            let payload = self.payloads.get(index).unwrap();
-            index += 1;
-            let mut buf = [0u8; 8];
-            buf.clone_from_slice(&payload.data[..8]);
-            let oset = unsafe { mem::transmute::<[u8; 8], u64>(buf) };
-            args[2] = format!("oset: {:#x}", oset);
-        } else {
-            args[2] = format!("oset: NULL");
+            SIG_BLOCK => ArgValue::Symbol("SIG_BLOCK".to_string()),
+            SIG_UNBLOCK => ArgValue::Symbol("SIG_UNBLOCK".to_string()),
+            SIG_SETMASK => ArgValue::Symbol("SIG_SETMASK".to_string()),
+            how => ArgValue::Raw(how),
+        };
+
+        for (inout, index) in [(crate::IN, 1), (crate::OUT, 2)] {
+            if self.head.ax[index] == 0 {
+                args[index] = ArgValue::Null;
+            } else if let Some(set) = self.find_payload_io(inout, index)
+                .and_then(|payload| parse_sigset(&payload.data)) {
+                args[index] = ArgValue::Struct(sigset_name(set));
+            }
         }
-        assert_eq!(index, self.payloads.len());
         ("rt_sigprocmask", 4, format!("{:#x}", self.result))
     }
 
-    fn do_mprotect(&self,args: &mut Vec<String>) -> (&'static str, usize, String) {
+    fn do_mprotect(&self,args: &mut [ArgValue]) -> (&'static str, usize, String) {
         if self.head.ax[0] == 0 {
-            args[0] = String::from("NULL");
+            args[0] = ArgValue::Null;
         }
-        args[2] = prot_name(self.head.ax[2]);
+        args[2] = ArgValue::Symbol(prot_name(self.head.ax[2]));
         if self.result <= 0 {
-            ("mprotect", 3, format!("{}", errno_name(self.result)))
+            ("mprotect", 3, errno_name(self.result).to_string())
         } else {
             ("mprotect", 3, format!("{:#x}", self.result))
         }
     }
 
-    fn do_write(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
-        args[0] = format!("{}", self.head.ax[0] as isize); // fd
-        if self.head.ax[0] == 1 || self.head.ax[0] == 2 {
-            if self.payloads.len() == 1 {
-                let payload = &self.payloads.first().unwrap();
-                assert_eq!(payload.inout, crate::OUT);
-                assert_eq!(payload.index, 1);
-                args[payload.index] = match CStr::from_bytes_until_nul(&payload.data) {
-                    Ok(content) => {
-                        format!("{:?}", content)
-                    }
-                    Err(_) => "[!parse_str_err!]".to_string(),
-                };
-            }
+    fn do_write(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Int(self.head.ax[0] as i64); // fd
+        if (self.head.ax[0] == 1 || self.head.ax[0] == 2) && self.payloads.len() == 1 {
+            let payload = &self.payloads.first().unwrap();
+            assert_eq!(payload.inout, crate::OUT);
+            assert_eq!(payload.index, 1);
+            args[payload.index] = ArgValue::Str(match CStr::from_bytes_until_nul(&payload.data) {
+                Ok(content) => {
+                    format!("{:?}", content)
+                }
+                Err(_) => "[!parse_str_err!]".to_string(),
+            });
         }
 
         ("write", 3, format!("{:#x}", self.result))
     }
 
-    fn do_read(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
-        args[0] = format!("{}", self.head.ax[0] as isize); // fd
-        if self.head.ax[0] == 0 {
-            if self.payloads.len() == 1 {
-                let payload = &self.payloads.first().unwrap();
-                assert_eq!(payload.inout, crate::OUT);
-                assert_eq!(payload.index, 1);
+    fn do_read(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Int(self.head.ax[0] as i64); // fd
+        if self.head.ax[0] == 0 && self.payloads.len() == 1 {
+            let payload = &self.payloads.first().unwrap();
+            assert_eq!(payload.inout, crate::OUT);
+            assert_eq!(payload.index, 1);
 
-                args[payload.index] = match CStr::from_bytes_until_nul(&payload.data) {
-                    Ok(content) => {
-                        format!("{:?}", content)
-                    }
-                    Err(_) => "[!parse_str_err!]".to_string(),
-                };
-            }
+            args[payload.index] = ArgValue::Str(match CStr::from_bytes_until_nul(&payload.data) {
+                Ok(content) => {
+                    format!("{:?}", content)
+                }
+                Err(_) => "[!parse_str_err!]".to_string(),
+            });
         }
 
         ("read", 3, format!("{:#x}", self.result))
     }
 
-    fn do_execve(&self, args: &mut Vec<String>) -> (&'static str, usize, String) {
+    /// Record of a trap other than ecall, e.g. a page fault.
+    pub fn is_trap(&self) -> bool {
+        !matches!(self.signal, SigStage::Enter(_)) && self.head.cause != USER_ECALL
+    }
+
+    pub fn find_payload(&self, index: usize) -> Option<&TracePayload> {
+        self.payloads.iter().find(|payload| payload.index == index)
+    }
+
+    /// Payloads of IN and OUT may share an index, e.g. the pollfd array.
+    pub fn find_payload_io(&self, inout: u64, index: usize) -> Option<&TracePayload> {
+        self.payloads.iter().find(|payload| payload.inout == inout && payload.index == index)
+    }
+
+    fn do_str(&self, args: &mut [ArgValue], index: usize) {
+        if let Some(payload) = self.find_payload_io(crate::IN, index) {
+            args[index] = ArgValue::Str(match CStr::from_bytes_until_nul(&payload.data) {
+                Ok(content) => format!("{:?}", content),
+                Err(_) => "[!parse_str_err!]".to_string(),
+            });
+        }
+    }
+
+    fn do_timespec(&self, args: &mut [ArgValue], index: usize) {
+        if self.head.ax[index] == 0 {
+            args[index] = ArgValue::Null;
+        } else if let Some(payload) = self.find_payload_io(crate::IN, index) {
+            if payload.data.len() >= 16 {
+                let mut buf = [0u8; 8];
+                buf.clone_from_slice(&payload.data[..8]);
+                let sec = i64::from_ne_bytes(buf);
+                buf.clone_from_slice(&payload.data[8..16]);
+                let nsec = i64::from_ne_bytes(buf);
+                args[index] = ArgValue::Struct(format!("{{tv_sec={}, tv_nsec={}}}", sec, nsec));
+            }
+        }
+    }
+
+    fn do_sockaddr(&self, args: &mut [ArgValue], index: usize) {
+        if self.head.ax[index] == 0 {
+            args[index] = ArgValue::Null;
+        } else if let Some(payload) = self.find_payload(index) {
+            args[index] = ArgValue::Struct(sockaddr_name(&payload.data));
+        }
+    }
+
+    fn do_buf(&self, args: &mut [ArgValue], index: usize) {
+        if let Some(payload) = self.find_payload(index) {
+            args[index] = ArgValue::Str(format!("{:?}", String::from_utf8_lossy(&payload.data)));
+        }
+    }
+
+    fn do_socket(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Symbol(domain_name(self.head.ax[0]));
+        args[1] = ArgValue::Symbol(sock_type_name(self.head.ax[1]));
+        args[2] = ArgValue::Symbol(protocol_name(self.head.ax[2]));
+        self.do_common("socket", 3)
+    }
+
+    fn do_socketpair(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Symbol(domain_name(self.head.ax[0]));
+        args[1] = ArgValue::Symbol(sock_type_name(self.head.ax[1]));
+        args[2] = ArgValue::Symbol(protocol_name(self.head.ax[2]));
+        if self.result == 0 {
+            if let Some((fd0, fd1)) = self.fd_pair(3) {
+                args[3] = ArgValue::Struct(format!("[{}, {}]", fd0, fd1));
+            }
+        }
+        self.do_common("socketpair", 4)
+    }
+
+    fn do_sockaddr_in(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        self.do_sockaddr(args, 1);
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        self.do_common(name, 3)
+    }
+
+    fn do_sockaddr_out(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result == 0 {
+            self.do_sockaddr(args, 1);
+        }
+        self.do_common(name, 3)
+    }
+
+    fn do_accept(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result >= 0 {
+            self.do_sockaddr(args, 1);
+        }
+        if self.head.ax[7] == SYS_ACCEPT4 {
+            args[3] = ArgValue::Symbol(accept_flag_name(self.head.ax[3]));
+            self.do_common(name, 4)
+        } else {
+            self.do_common(name, 3)
+        }
+    }
+
+    fn do_sendto(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        self.do_buf(args, 1);
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        args[3] = ArgValue::Symbol(msg_flag_name(self.head.ax[3]));
+        self.do_sockaddr(args, 4);
+        args[5] = ArgValue::Int(self.head.ax[5] as i64);
+        self.do_common("sendto", 6)
+    }
+
+    fn do_recvfrom(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result >= 0 {
+            self.do_buf(args, 1);
+            self.do_sockaddr(args, 4);
+        }
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        args[3] = ArgValue::Symbol(msg_flag_name(self.head.ax[3]));
+        self.do_common("recvfrom", 6)
+    }
+
+    fn do_sockopt(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        let (level, optname) = (self.head.ax[1], self.head.ax[2]);
+        args[1] = ArgValue::Symbol(sockopt_level_name(level));
+        args[2] = ArgValue::Symbol(sockopt_name(level, optname));
+        if self.head.ax[7] == SYS_SETSOCKOPT || self.result == 0 {
+            if let Some(payload) = self.find_payload(3) {
+                args[3] = ArgValue::Struct(sockopt_value(level, optname, &payload.data));
+            }
+        }
+        self.do_common(name, 5)
+    }
+
+    fn do_shutdown(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[1] = ArgValue::Symbol(shutdown_how_name(self.head.ax[1]));
+        self.do_common("shutdown", 2)
+    }
+
+    fn do_futex(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        let op = self.head.ax[1];
+        args[1] = ArgValue::Symbol(futex_op_name(op));
+        args[2] = ArgValue::Int(self.head.ax[2] as i32 as i64);
+        if futex_has_timeout(op) {
+            self.do_timespec(args, 3);
+        } else {
+            args[3] = ArgValue::Int(self.head.ax[3] as i32 as i64);
+        }
+        if self.head.ax[5] == FUTEX_BITSET_MATCH_ANY {
+            args[5] = ArgValue::Symbol(String::from("FUTEX_BITSET_MATCH_ANY"));
+        }
+        let argc = match futex_cmd(op) {
+            FUTEX_WAKE | FUTEX_FD => 3,
+            FUTEX_UNLOCK_PI | FUTEX_TRYLOCK_PI => 2,
+            FUTEX_WAIT | FUTEX_LOCK_PI | FUTEX_LOCK_PI2 => 4,
+            FUTEX_REQUEUE | FUTEX_WAIT_REQUEUE_PI => 5,
+            _ => 6,
+        };
+        self.do_common("futex", argc)
+    }
+
+    fn do_ppoll(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if let Some(payload) = self.find_payload_io(crate::IN, 0) {
+            args[0] = ArgValue::Struct(pollfds_name(&parse_pollfds(&payload.data)));
+        }
+        args[1] = ArgValue::Int(self.head.ax[1] as i64);
+        self.do_timespec(args, 2);
+        if self.head.ax[3] == 0 {
+            args[3] = ArgValue::Null;
+        }
+        args[4] = ArgValue::Int(self.head.ax[4] as i64);
+        let (name, argc, result) = self.do_common("ppoll", 5);
+        if self.result == 0 {
+            return (name, argc, format!("{} (Timeout)", result));
+        }
+        match self.find_payload_io(crate::OUT, 0) {
+            Some(payload) if self.result > 0 => {
+                let revents = pollfds_revents_name(&parse_pollfds(&payload.data));
+                (name, argc, format!("{} ({})", result, revents))
+            },
+            _ => (name, argc, result),
+        }
+    }
+
+    fn do_pselect6(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        let nfds = self.head.ax[0] as usize;
+        args[0] = ArgValue::Int(nfds as i64);
+        for (index, arg) in args.iter_mut().enumerate().take(4).skip(1) {
+            if self.head.ax[index] == 0 {
+                *arg = ArgValue::Null;
+            } else if let Some(payload) = self.find_payload_io(crate::IN, index) {
+                *arg = ArgValue::Struct(fd_set_name(&payload.data, nfds));
+            }
+        }
+        self.do_timespec(args, 4);
+        let (name, argc, result) = self.do_common("pselect6", 6);
+        if self.result == 0 {
+            return (name, argc, format!("{} (Timeout)", result));
+        }
+        if self.result < 0 {
+            return (name, argc, result);
+        }
+        let mut sets = vec![];
+        for (index, set) in [(1, "in"), (2, "out"), (3, "except")] {
+            if let Some(payload) = self.find_payload_io(crate::OUT, index) {
+                sets.push(format!("{} {}", set, fd_set_name(&payload.data, nfds)));
+            }
+        }
+        if sets.is_empty() {
+            (name, argc, result)
+        } else {
+            (name, argc, format!("{} ({})", result, sets.join(", ")))
+        }
+    }
+
+    fn do_epoll_create1(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Symbol(epoll_create_flag_name(self.head.ax[0]));
+        self.do_common("epoll_create1", 1)
+    }
+
+    fn do_epoll_ctl(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[1] = ArgValue::Symbol(epoll_ctl_op_name(self.head.ax[1]));
+        if self.head.ax[3] == 0 {
+            args[3] = ArgValue::Null;
+        } else if let Some(payload) = self.find_payload_io(crate::IN, 3) {
+            args[3] = ArgValue::Struct(epoll_event_struct_name(&payload.data));
+        }
+        self.do_common("epoll_ctl", 4)
+    }
+
+    fn do_epoll_pwait(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result > 0 {
+            if let Some(payload) = self.find_payload_io(crate::OUT, 1) {
+                args[1] = ArgValue::Struct(epoll_events_name(&payload.data, self.result as usize));
+            }
+        }
+        args[2] = ArgValue::Int(self.head.ax[2] as i32 as i64);
+        args[3] = ArgValue::Int(self.head.ax[3] as i32 as i64);
+        if self.head.ax[4] == 0 {
+            args[4] = ArgValue::Null;
+        }
+        args[5] = ArgValue::Int(self.head.ax[5] as i64);
+        self.do_common("epoll_pwait", 6)
+    }
+
+    fn do_pipe2(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result == 0 {
+            if let Some((rfd, wfd)) = self.fd_pair(0) {
+                args[0] = ArgValue::Struct(format!("[{}, {}]", rfd, wfd));
+            }
+        }
+        args[1] = ArgValue::Symbol(pipe_flag_name(self.head.ax[1]));
+        self.do_common("pipe2", 2)
+    }
+
+    fn do_eventfd2(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Int(self.head.ax[0] as u32 as i64);
+        args[1] = ArgValue::Symbol(eventfd_flag_name(self.head.ax[1]));
+        self.do_common("eventfd2", 2)
+    }
+
+    fn do_memfd_create(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        self.do_str(args, 0);
+        args[1] = ArgValue::Symbol(memfd_flag_name(self.head.ax[1]));
+        self.do_common("memfd_create", 2)
+    }
+
+    /// shmget(key, size, flg), semget(key, nsems, flg) and msgget(key, flg)
+    fn do_ipc_get(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        args[0] = ArgValue::Symbol(ipc_key_name(self.head.ax[0]));
+        if self.head.ax[7] == SYS_MSGGET {
+            args[1] = ArgValue::Symbol(ipc_get_flag_name(self.head.ax[1]));
+            return self.do_common(name, 2);
+        }
+        if self.head.ax[7] == SYS_SEMGET {
+            args[1] = ArgValue::Int(self.head.ax[1] as i64);
+        }
+        args[2] = ArgValue::Symbol(ipc_get_flag_name(self.head.ax[2]));
+        self.do_common(name, 3)
+    }
+
+    fn do_ipc_ctl(
+        &self, name: &'static str, cmd: usize, argc: usize, args: &mut [ArgValue]
+    ) -> (&'static str, usize, String) {
+        args[cmd] = ArgValue::Symbol(ipc_cmd_name(self.head.ax[cmd]));
+        self.do_common(name, argc)
+    }
+
+    fn do_shmat(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.head.ax[1] == 0 {
+            args[1] = ArgValue::Null;
+        }
+        args[2] = ArgValue::Symbol(shmat_flag_name(self.head.ax[2]));
+        self.do_common("shmat", 3)
+    }
+
+    fn do_semop(&self, name: &'static str, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if let Some(payload) = self.find_payload_io(crate::IN, 1) {
+            args[1] = ArgValue::Struct(sembufs_name(&payload.data));
+        }
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        if self.head.ax[7] == SYS_SEMTIMEDOP {
+            self.do_timespec(args, 3);
+            return self.do_common(name, 4);
+        }
+        self.do_common(name, 3)
+    }
+
+    fn do_msgsnd(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if let Some(payload) = self.find_payload_io(crate::IN, 1) {
+            args[1] = ArgValue::Struct(msgbuf_name(&payload.data));
+        }
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        args[3] = ArgValue::Symbol(ipc_msg_flag_name(self.head.ax[3]));
+        self.do_common("msgsnd", 4)
+    }
+
+    fn do_msgrcv(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result >= 0 {
+            if let Some(payload) = self.find_payload_io(crate::OUT, 1) {
+                args[1] = ArgValue::Struct(msgbuf_name(&payload.data));
+            }
+        }
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        args[3] = ArgValue::Int(self.head.ax[3] as i64);
+        args[4] = ArgValue::Symbol(ipc_msg_flag_name(self.head.ax[4]));
+        self.do_common("msgrcv", 5)
+    }
+
+    fn do_mq_open(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        self.do_str(args, 0);
+        let oflag = self.head.ax[1];
+        args[1] = ArgValue::Symbol(oflag_name(oflag));
+        if oflag & O_CREAT == 0 {
+            return self.do_common("mq_open", 2);
+        }
+        args[2] = ArgValue::Symbol(format!("{:#o}", self.head.ax[2]));
+        if self.head.ax[3] == 0 {
+            args[3] = ArgValue::Null;
+        } else if let Some(payload) = self.find_payload_io(crate::IN, 3) {
+            args[3] = ArgValue::Struct(mq_attr_name(&payload.data));
+        }
+        self.do_common("mq_open", 4)
+    }
+
+    fn do_mq_unlink(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        self.do_str(args, 0);
+        self.do_common("mq_unlink", 1)
+    }
+
+    fn do_mq_timedsend(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        self.do_buf(args, 1);
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        args[3] = ArgValue::Int(self.head.ax[3] as i64);
+        self.do_timespec(args, 4);
+        self.do_common("mq_timedsend", 5)
+    }
+
+    fn do_mq_timedreceive(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if self.result >= 0 {
+            self.do_buf(args, 1);
+        }
+        args[2] = ArgValue::Int(self.head.ax[2] as i64);
+        self.do_timespec(args, 4);
+        self.do_common("mq_timedreceive", 5)
+    }
+
+    fn do_mq_getsetattr(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
+        if let Some(payload) = self.find_payload_io(crate::IN, 1) {
+            args[1] = ArgValue::Struct(mq_attr_name(&payload.data));
+        } else if self.head.ax[1] == 0 {
+            args[1] = ArgValue::Null;
+        }
+        if self.result == 0 {
+            if let Some(payload) = self.find_payload_io(crate::OUT, 2) {
+                args[2] = ArgValue::Struct(mq_attr_name(&payload.data));
+            }
+        }
+        self.do_common("mq_getsetattr", 3)
+    }
+
+    /// Two fds written back by socketpair/pipe2 as `int[2]`.
+    pub fn fd_pair(&self, index: usize) -> Option<(i32, i32)> {
+        let payload = self.find_payload(index)?;
+        if payload.data.len() < 8 {
+            return None;
+        }
+        let mut buf = [0u8; 4];
+        buf.clone_from_slice(&payload.data[..4]);
+        let fd0 = i32::from_ne_bytes(buf);
+        buf.clone_from_slice(&payload.data[4..8]);
+        let fd1 = i32::from_ne_bytes(buf);
+        Some((fd0, fd1))
+    }
+
+    fn do_execve(&self, args: &mut [ArgValue]) -> (&'static str, usize, String) {
         let mut argv = Vec::new();
         let mut envp = Vec::new();
         for payload in &self.payloads {
             if payload.index == 0 {
-                args[payload.index] = match CStr::from_bytes_until_nul(&payload.data) {
+                args[payload.index] = ArgValue::Str(match CStr::from_bytes_until_nul(&payload.data) {
                     Ok(content) => {
                         format!("{:?}", content)
                     }
                     Err(_) => "[!parse_str_err!]".to_string(),
-                };
+                });
             }else if payload.index == 1 {
                 argv.push(match CStr::from_bytes_until_nul(&payload.data) {
                     Ok(content) => {
@@ -511,8 +1034,8 @@ impl TraceEvent {
                 })
             }
         }
-        args[1] = format!("{{{}}}", argv.join(", "));
-        args[2] = format!("{{{}}}", envp.join(", "));
+        args[1] = ArgValue::Struct(format!("{{{}}}", argv.join(", ")));
+        args[2] = ArgValue::Struct(format!("{{{}}}", envp.join(", ")));
         ("execve",3, format!("{:#x}", self.result))
     }
 }
@@ -528,42 +1051,33 @@ impl Display for TraceEvent {
             },
             _ => (),
         }
-        assert_eq!(self.head.cause, USER_ECALL);
-
-        let mut args = self.head.ax[..7]
-            .iter()
-            .map(|arg| format!("{:#x}", arg))
-            .collect::<Vec<_>>();
-
-        let (sysname, argc, result) = self.handle_syscall(&mut args);
-        let sysname = if sysname.len() > 0 {
-            sysname.to_owned()
-        } else {
-            format!("sys_{}", self.head.ax[7])
-        };
+        if self.head.cause != USER_ECALL {
+            return write!(fmt, "{}, epc: {:#x}, usp: {:#x}", trap_name(self), self.head.epc, self.head.usp);
+        }
 
-        write!(
-            fmt,
-            "{}({}) -> {}, usp: {:#x}",
-            sysname,
-            args[..argc].join(", "),
-            result,
-            self.head.usp
-        )
+        write!(fmt, "{}, usp: {:#x}", decode_event(self), self.head.usp)
     }
 }
 
-pub fn parse_sigaction(evt: &TraceEvent) -> Option<(SigAction, usize)> {
-    let payload = evt.payloads.first()?;
-    let mut buf = [0u8; 24];
-    buf.clone_from_slice(&payload.data[..24]);
-    let sigaction = unsafe { mem::transmute::<[u8; 24], SigAction>(buf) };
-    Some((sigaction, payload.index))
+/// New action (IN) or old action (OUT) of rt_sigaction.
+pub fn parse_sigaction(evt: &TraceEvent, inout: u64) -> Option<SigAction> {
+    let index = if inout == crate::IN { 1 } else { 2 };
+    let payload = evt.find_payload_io(inout, index)?;
+    SigAction::from_bytes(&payload.data)
+}
+
+pub fn print_events(tid: u64, events: &[TraceEvent]) {
+    print_task(&format!("{:#x}", tid), events, true);
 }
 
-pub fn print_events(tid: u64, events: &Vec<TraceEvent>) {
-    println!("Task[{:#x}] ========>", tid);
-    for (idx, evt) in events.iter().enumerate() {
+/// Print events of a task, numbered without traps if they are left out.
+/// A trap interrupted by a signal is kept for the exit of the signal.
+pub(crate) fn print_task(name: &str, events: &[TraceEvent], traps: bool) {
+    println!("Task[{}] ========>", name);
+    let events = events.iter().filter(|evt| {
+        traps || !evt.is_trap() || !matches!(evt.signal, SigStage::Empty)
+    });
+    for (idx, evt) in events.enumerate() {
         println!("[{}]: {}", idx, evt);
     }
     println!();
@@ -573,7 +1087,6 @@ pub fn parse_event(reader: &mut BufReader<File>, level: usize) -> Result<TraceEv
     let mut buf = [0u8; TE_SIZE];
     reader.read_exact(&mut buf)?;
     let head = unsafe { mem::transmute::<[u8; TE_SIZE], TraceHead>(buf) };
-    assert_eq!(head.cause, USER_ECALL);
 
     //println!("a7: {} total: {}", head.ax[7], head.totalsize);
     let payloads = if head.totalsize as usize > head.headsize as usize {
@@ -588,17 +1101,84 @@ pub fn parse_event(reader: &mut BufReader<File>, level: usize) -> Result<TraceEv
 
     let evt = TraceEvent {
         head,
+        out_head: None,
         result: 0,
         payloads,
         signal: SigStage::Empty,
         raw_fmt: false,
-        level: level,
+        level,
+        seq: 0,
+        offset: 0,
+        tids: TidMap::default(),
     };
     Ok(evt)
 }
 
-fn parse_usize(s: &str) -> usize {
-    let s = s.trim();
-    assert!(s.starts_with("0x"), "input: {}", s);
-    usize::from_str_radix(&s[2..], 16).unwrap()
+/// Iterator over the events of a trace file.
+pub struct TraceReader {
+    reader: BufReader<File>,
+    remain: usize,
+    offset: u64,
+    level: usize,
+    tids: TidMap,
 }
+
+impl TraceReader {
+    pub fn open(fname: &str, level: usize) -> Result<Self> {
+        let f = File::open(fname)?;
+        let remain = f.metadata()?.len() as usize;
+        Ok(Self {
+            reader: BufReader::new(f),
+            remain,
+            offset: 0,
+            level,
+            tids: TidMap::default(),
+        })
+    }
+
+    /// File offset of the next event.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl Iterator for TraceReader {
+    type Item = Result<TraceEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remain < TE_SIZE {
+            return None;
+        }
+        let mut evt = match parse_event(&mut self.reader, self.level) {
+            Ok(evt) => evt,
+            Err(err) => {
+                self.remain = 0;
+                return Some(Err(err));
+            },
+        };
+        let head = &evt.head;
+        if head.magic != LK_MAGIC
+            || head.headsize != TE_SIZE as u16
+            || head.totalsize < head.headsize as u32 {
+            self.remain = 0;
+            return Some(Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("bad event head at offset {:#x}", self.offset),
+            )));
+        }
+        // Tasks are named as their tids show up: returned to the parent by
+        // clone, or to the task itself.
+        let tid = head.ax[0] as i64;
+        if head.inout == crate::OUT && head.cause == USER_ECALL && tid > 0
+            && matches!(head.ax[7], SYS_CLONE | SYS_CLONE3 | SYS_GETTID | SYS_SET_TID_ADDRESS) {
+            self.tids.add(tid);
+        }
+        let advance = head.totalsize as usize;
+        evt.tids = self.tids.clone();
+        evt.offset = self.offset;
+        self.remain = self.remain.saturating_sub(advance);
+        self.offset += advance as u64;
+        Some(Ok(evt))
+    }
+}
+
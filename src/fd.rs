@@ -0,0 +1,170 @@
+//! File descriptor tracking.
+
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+use crate::errno::EINPROGRESS;
+use crate::event::TraceEvent;
+use crate::socket::{domain_name, sock_type_name, sockaddr_name, AF_UNSPEC};
+use crate::sysno::*;
+
+pub(crate) const F_DUPFD: u64 = 0;
+pub(crate) const F_DUPFD_CLOEXEC: u64 = 1030;
+
+/// What an open fd refers to.
+#[derive(Clone, Debug)]
+pub enum FdKind {
+    File(String),
+    Socket {
+        domain: u64,
+        ty: u64,
+        local: Option<String>,
+        peer: Option<String>,
+    },
+    Epoll,
+    /// Read or write end of a pipe.
+    Pipe(bool),
+    EventFd,
+    MemFd(String),
+    MQueue(String),
+}
+
+impl Display for FdKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FdKind::File(path) => write!(f, "file {:?}", path),
+            FdKind::Socket { domain, ty, local, peer } => {
+                write!(f, "socket {} {}", domain_name(*domain), sock_type_name(*ty))?;
+                if let Some(local) = local {
+                    write!(f, " local {}", local)?;
+                }
+                if let Some(peer) = peer {
+                    write!(f, " peer {}", peer)?;
+                }
+                Ok(())
+            },
+            FdKind::Epoll => write!(f, "epoll"),
+            FdKind::Pipe(true) => write!(f, "pipe read end"),
+            FdKind::Pipe(false) => write!(f, "pipe write end"),
+            FdKind::EventFd => write!(f, "eventfd"),
+            FdKind::MemFd(name) => write!(f, "memfd {:?}", name),
+            FdKind::MQueue(name) => write!(f, "mqueue {:?}", name),
+        }
+    }
+}
+
+/// Fd table of a task, rebuilt from completed syscalls.
+/// Fds opened before the trace started are unknown.
+#[derive(Clone, Debug, Default)]
+pub struct FdTable {
+    fds: BTreeMap<i64, FdKind>,
+}
+
+impl FdTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, fd: i64) -> Option<&FdKind> {
+        self.fds.get(&fd)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&i64, &FdKind)> {
+        self.fds.iter()
+    }
+
+    /// Apply the effect of a completed syscall.
+    pub fn update(&mut self, evt: &TraceEvent) {
+        let fd = evt.head.ax[0] as i64;
+        match evt.head.ax[7] {
+            SYS_OPENAT if evt.result >= 0 => {
+                self.fds.insert(evt.result, FdKind::File(payload_str(evt, 1)));
+            },
+            SYS_CLOSE if evt.result == 0 => {
+                self.fds.remove(&fd);
+            },
+            SYS_DUP3 if evt.result >= 0 => {
+                self.dup(fd, evt.result);
+            },
+            SYS_FCNTL if evt.result >= 0
+                && (evt.head.ax[1] == F_DUPFD || evt.head.ax[1] == F_DUPFD_CLOEXEC) => {
+                self.dup(fd, evt.result);
+            },
+            SYS_SOCKET if evt.result >= 0 => {
+                self.fds.insert(evt.result, FdKind::Socket {
+                    domain: evt.head.ax[0],
+                    ty: evt.head.ax[1],
+                    local: None,
+                    peer: None,
+                });
+            },
+            SYS_SOCKETPAIR if evt.result == 0 => {
+                if let Some((fd0, fd1)) = evt.fd_pair(3) {
+                    for fd in [fd0, fd1] {
+                        self.fds.insert(fd as i64, FdKind::Socket {
+                            domain: evt.head.ax[0],
+                            ty: evt.head.ax[1],
+                            local: None,
+                            peer: None,
+                        });
+                    }
+                }
+            },
+            SYS_ACCEPT | SYS_ACCEPT4 if evt.result >= 0 => {
+                let (domain, ty, local) = match self.fds.get(&fd) {
+                    Some(FdKind::Socket { domain, ty, local, .. }) => (*domain, *ty, local.clone()),
+                    _ => (AF_UNSPEC, 0, None),
+                };
+                let peer = evt.find_payload(1).map(|payload| sockaddr_name(&payload.data));
+                self.fds.insert(evt.result, FdKind::Socket { domain, ty, local, peer });
+            },
+            SYS_BIND if evt.result == 0 => {
+                if let Some(FdKind::Socket { local, .. }) = self.fds.get_mut(&fd) {
+                    *local = evt.find_payload(1).map(|payload| sockaddr_name(&payload.data));
+                }
+            },
+            SYS_CONNECT if evt.result == 0 || evt.result == -(EINPROGRESS as i64) => {
+                if let Some(FdKind::Socket { peer, .. }) = self.fds.get_mut(&fd) {
+                    *peer = evt.find_payload(1).map(|payload| sockaddr_name(&payload.data));
+                }
+            },
+            SYS_EPOLL_CREATE1 if evt.result >= 0 => {
+                self.fds.insert(evt.result, FdKind::Epoll);
+            },
+            SYS_PIPE2 if evt.result == 0 => {
+                if let Some((rfd, wfd)) = evt.fd_pair(0) {
+                    self.fds.insert(rfd as i64, FdKind::Pipe(true));
+                    self.fds.insert(wfd as i64, FdKind::Pipe(false));
+                }
+            },
+            SYS_EVENTFD2 if evt.result >= 0 => {
+                self.fds.insert(evt.result, FdKind::EventFd);
+            },
+            SYS_MEMFD_CREATE if evt.result >= 0 => {
+                self.fds.insert(evt.result, FdKind::MemFd(payload_str(evt, 0)));
+            },
+            SYS_MQ_OPEN if evt.result >= 0 => {
+                self.fds.insert(evt.result, FdKind::MQueue(payload_str(evt, 0)));
+            },
+            _ => (),
+        }
+    }
+
+    fn dup(&mut self, oldfd: i64, newfd: i64) {
+        match self.fds.get(&oldfd).cloned() {
+            Some(kind) => {
+                self.fds.insert(newfd, kind);
+            },
+            None => {
+                self.fds.remove(&newfd);
+            },
+        }
+    }
+}
+
+fn payload_str(evt: &TraceEvent, index: usize) -> String {
+    evt.find_payload(index)
+        .and_then(|payload| CStr::from_bytes_until_nul(&payload.data).ok())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
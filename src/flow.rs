@@ -0,0 +1,1186 @@
+//! Flow reconstruction.
+//!
+//! A trace is a stream of IN (request) and OUT (reply) records of all tasks.
+//! [`FlowBuilder`] pairs them into completed [`TraceEvent`]s per task, and
+//! tracks clone replies, signal entry/exit and task exit.
+//!
+//! A task ends with exit, or with exit_group/execve of any thread in its
+//! thread group. An id seen again afterwards starts a new flow with the
+//! next generation.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::io::Result;
+use std::sync::{Arc, Mutex};
+use crate::abi::{check_event, Clobber, ECALL_SIZE};
+use crate::decode::MAX_ERRNO;
+use crate::errno::{errno_name, UNKNOWN_ERRNO};
+use crate::event::{parse_sigaction, print_task, SigStage, TraceEvent, TraceHead, TraceReader};
+use crate::fd::FdTable;
+use crate::sched::{CloneArgs, CLONE_SETTLS, CLONE_SIGHAND, CLONE_THREAD, CLONE_VM};
+use crate::signal::{
+    parse_sigset, sigmask, sigset_name, SigHand, SA_NODEFER, SIG_BLOCK, SIG_SETMASK,
+    SIG_UNBLOCK, UNBLOCKABLE,
+};
+use crate::sysno::*;
+use crate::{IN, OUT};
+
+/// Events of one task.
+pub struct TraceFlow {
+    /// Task id in the trace (sscratch)
+    pub tid: u64,
+    /// Number of earlier flows with the same tid.
+    pub generation: u32,
+    /// Index of the record which started the flow.
+    pub start: usize,
+    /// Trace tid of the thread group leader.
+    pub tgid: u64,
+    /// Tid as seen by the task itself, from clone/gettid/set_tid_address.
+    pub kernel_tid: Option<i64>,
+    /// Trace tid of the task which cloned this one.
+    pub parent: Option<u64>,
+    pub clone_flags: Option<u64>,
+    /// Trace tids of the tasks cloned by this one.
+    pub children: Vec<u64>,
+    pub events: Vec<TraceEvent>,
+    pub signal_stack: Vec<TraceEvent>,
+    /// Blocked signals, as modeled from the trace.
+    pub sigmask: u64,
+    /// Masks to restore by rt_sigreturn, along with signal_stack.
+    saved_sigmask: Vec<u64>,
+    pub fds: FdTable,
+    /// Signal dispositions of the process.
+    pub sighand: Arc<Mutex<SigHand>>,
+    pub exit: Option<ExitReason>,
+    /// Some records of the task are missing, with placeholders for them.
+    pub partial: bool,
+}
+
+/// How a task has ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    Exit,
+    ExitGroup,
+    /// Killed by exit_group of another thread.
+    Killed { by: u64 },
+    /// Killed by execve of another thread.
+    Dethreaded { by: u64 },
+    /// The tid shows up as a new task without any exit record.
+    Reused,
+}
+
+impl Default for TraceFlow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceFlow {
+    pub fn new() -> Self {
+        Self {
+            tid: 0,
+            generation: 0,
+            start: 0,
+            tgid: 0,
+            kernel_tid: None,
+            parent: None,
+            clone_flags: None,
+            children: Vec::new(),
+            events: Vec::new(),
+            signal_stack: Vec::new(),
+            sigmask: 0,
+            saved_sigmask: Vec::new(),
+            fds: FdTable::new(),
+            sighand: Arc::new(Mutex::new(SigHand::new())),
+            exit: None,
+            partial: false,
+        }
+    }
+
+    /// Tid, with the generation if the tid has been reused.
+    pub fn name(&self) -> String {
+        if self.generation == 0 {
+            format!("{:#x}", self.tid)
+        } else {
+            format!("{:#x}#{}", self.tid, self.generation)
+        }
+    }
+
+    /// Print events, leaving out traps unless asked.
+    pub fn print(&self, traps: bool) {
+        if self.partial {
+            print_task(&format!("{} partial", self.name()), &self.events, traps);
+        } else {
+            print_task(&self.name(), &self.events, traps);
+        }
+    }
+
+    /// The last event is interrupted by a signal whose handler is entered.
+    fn enter_signal(&mut self, signum: u64) {
+        // Block sa_mask and the signal itself while handling it.
+        self.saved_sigmask.push(self.sigmask);
+        let mut sighand = self.sighand.lock().unwrap();
+        if let Some(action) = sighand.get(signum) {
+            self.sigmask |= action.mask as u64;
+            if action.flags & SA_NODEFER == 0 {
+                self.sigmask |= sigmask(signum);
+            }
+            self.sigmask &= !UNBLOCKABLE;
+        }
+        sighand.deliver(signum);
+        drop(sighand);
+
+        let mut last = self.events.pop().unwrap();
+        last.signal = SigStage::Exit(signum);
+        self.signal_stack.push(last);
+
+        let mut sig_req = TraceEvent {
+            signal: SigStage::Enter(signum),
+            ..Default::default()
+        };
+        sig_req.head.inout = OUT;
+        sig_req.head.ax[0] = signum;
+        self.events.push(sig_req);
+    }
+
+    /// Check oset of the last rt_sigprocmask against the modeled mask,
+    /// then apply the new set.
+    fn sigprocmask(&mut self) -> Vec<Anomaly> {
+        let tid = self.tid;
+        let evt = match self.events.last() {
+            Some(evt) => evt,
+            None => return vec![],
+        };
+        let how = evt.head.ax[0];
+        let mut anomalies = vec![];
+        if !matches!(how, SIG_BLOCK | SIG_UNBLOCK | SIG_SETMASK) {
+            anomalies.push(Anomaly::BadSigprocmaskHow { tid, how, result: evt.result });
+            return anomalies;
+        }
+        if evt.result != 0 {
+            return anomalies;
+        }
+        if evt.head.ax[2] != 0 {
+            let oset = evt.find_payload_io(OUT, 2).and_then(|payload| parse_sigset(&payload.data));
+            if let Some(oset) = oset {
+                if oset != self.sigmask {
+                    anomalies.push(Anomaly::StaleSigmask { tid, expected: self.sigmask, actual: oset });
+                }
+            }
+        }
+        if evt.head.ax[1] != 0 {
+            let nset = evt.find_payload_io(IN, 1).and_then(|payload| parse_sigset(&payload.data));
+            if let Some(nset) = nset {
+                self.sigmask = match how {
+                    SIG_BLOCK => self.sigmask | nset,
+                    SIG_UNBLOCK => self.sigmask & !nset,
+                    _ => nset,
+                } & !UNBLOCKABLE;
+            }
+        }
+        anomalies
+    }
+}
+
+/// Something unexpected in the record stream.
+#[derive(Clone, Debug)]
+pub enum Anomaly {
+    /// A new request came before the last one returned.
+    Unfinished { tid: u64, sysno: u64 },
+    /// The reply is for another syscall than the pending request.
+    Unmatched { tid: u64, out_sysno: u64, in_sysno: u64 },
+    /// A reply without any request in its task.
+    OrphanOut { tid: u64, sysno: u64 },
+    /// rt_sigreturn without a signal being handled.
+    NoSignalFrame { tid: u64 },
+    /// No pending clone request matches the first reply of a new task.
+    UnmatchedClone { tid: u64 },
+    /// Several pending clone requests match a new task, and none of them
+    /// has returned its tid; the task is left without parent.
+    AmbiguousClone { tid: u64, parents: Vec<u64> },
+    /// Clone returned another tid than the child reports for itself.
+    CloneTidMismatch { tid: u64, expected: i64, actual: i64 },
+    /// execve has returned to the address of a signal handler.
+    ExecAtHandler { tid: u64, epc: u64 },
+    /// A new task took the tid of a task which has not exited.
+    TidReused { tid: u64 },
+    /// rt_sigprocmask returned an old mask other than the modeled one.
+    StaleSigmask { tid: u64, expected: u64, actual: u64 },
+    /// rt_sigprocmask with an invalid how.
+    BadSigprocmaskHow { tid: u64, how: u64, result: i64 },
+    /// The kernel has not preserved registers across a syscall.
+    AbiViolation { tid: u64, sysno: u64, clobbers: Vec<Clobber> },
+    /// A syscall failed with an errno without name.
+    UnknownErrno { tid: u64, sysno: u64, errno: i64 },
+}
+
+impl Anomaly {
+    /// Short name of the kind, for counting.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Anomaly::Unfinished { .. } => "unfinished",
+            Anomaly::Unmatched { .. } => "unmatched",
+            Anomaly::OrphanOut { .. } => "orphan_out",
+            Anomaly::NoSignalFrame { .. } => "no_signal_frame",
+            Anomaly::UnmatchedClone { .. } => "unmatched_clone",
+            Anomaly::AmbiguousClone { .. } => "ambiguous_clone",
+            Anomaly::CloneTidMismatch { .. } => "clone_tid_mismatch",
+            Anomaly::ExecAtHandler { .. } => "exec_at_handler",
+            Anomaly::TidReused { .. } => "tid_reused",
+            Anomaly::StaleSigmask { .. } => "stale_sigmask",
+            Anomaly::BadSigprocmaskHow { .. } => "bad_sigprocmask_how",
+            Anomaly::AbiViolation { .. } => "abi_violation",
+            Anomaly::UnknownErrno { .. } => "unknown_errno",
+        }
+    }
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::Unfinished { sysno, .. } => {
+                write!(f, "might be killed: {}", sysno)
+            },
+            Anomaly::Unmatched { out_sysno, in_sysno, .. } => {
+                write!(f, "unmatch: {} != {}", out_sysno, in_sysno)
+            },
+            Anomaly::OrphanOut { sysno, .. } => {
+                write!(f, "no request for reply: {}", sysno)
+            },
+            Anomaly::NoSignalFrame { .. } => {
+                write!(f, "rt_sigreturn without signal")
+            },
+            Anomaly::UnmatchedClone { tid } => {
+                write!(f, "no clone request for new task {:#x}", tid)
+            },
+            Anomaly::AmbiguousClone { tid, parents } => {
+                let parents = parents.iter().map(|p| format!("{:#x}", p)).collect::<Vec<_>>();
+                write!(f, "ambiguous parent of new task {:#x}: [{}]", tid, parents.join(", "))
+            },
+            Anomaly::CloneTidMismatch { tid, expected, actual } => {
+                write!(f, "task {:#x} is tid {} but clone returned {}", tid, actual, expected)
+            },
+            Anomaly::ExecAtHandler { epc, .. } => {
+                write!(f, "execve returned to signal handler: {:#x}", epc)
+            },
+            Anomaly::TidReused { tid } => {
+                write!(f, "tid reused before exit: {:#x}", tid)
+            },
+            Anomaly::StaleSigmask { expected, actual, .. } => {
+                write!(f, "stale sigmask: oset {} != {}", sigset_name(*actual), sigset_name(*expected))
+            },
+            Anomaly::BadSigprocmaskHow { how, result, .. } => {
+                write!(f, "rt_sigprocmask with bad how: {:#x} -> {}", how, result)
+            },
+            Anomaly::AbiViolation { sysno, clobbers, .. } => {
+                let clobbers = clobbers.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+                write!(f, "registers clobbered by {}: {}", sysno, clobbers.join(", "))
+            },
+            Anomaly::UnknownErrno { sysno, errno, .. } => {
+                write!(f, "unknown errno of {}: {}", sysno, errno)
+            },
+        }
+    }
+}
+
+/// An anomaly with where it is found.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    /// File offset of the record
+    pub offset: u64,
+    pub tid: u64,
+    /// Index of the event in the flow of the task
+    pub index: usize,
+    pub anomaly: Anomaly,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#x}: Task[{:#x}] [{}]: {}", self.offset, self.tid, self.index, self.anomaly)
+    }
+}
+
+/// Callbacks of [`FlowBuilder`]. All of them do nothing by default.
+pub trait FlowObserver {
+    fn on_task_start(&mut self, _flow: &TraceFlow) {}
+    /// A syscall of the task has returned.
+    fn on_syscall(&mut self, _tid: u64, _evt: &TraceEvent) {}
+    fn on_task_exit(&mut self, _flow: &TraceFlow) {}
+    fn on_anomaly(&mut self, _anomaly: &Anomaly) {}
+    /// An anomaly along with where it is in the trace. Forwards the
+    /// anomaly to [`on_anomaly`](Self::on_anomaly) by default.
+    fn on_diagnostic(&mut self, diag: &Diagnostic) {
+        self.on_anomaly(&diag.anomaly);
+    }
+}
+
+fn is_clone(evt: &TraceEvent) -> bool {
+    matches!(evt.head.ax[7], SYS_CLONE | SYS_CLONE3)
+}
+
+/// Request made up from a reply whose request is missing.
+fn placeholder(evt: &TraceEvent) -> TraceEvent {
+    let mut req = evt.clone();
+    req.head.ax[0] = req.head.orig_a0;
+    req.head.epc = req.head.epc.wrapping_sub(ECALL_SIZE);
+    req.head.inout = IN;
+    req.payloads.clear();
+    req
+}
+
+fn report<O: FlowObserver>(observer: &mut O, offset: u64, tid: u64, index: usize, anomaly: Anomaly) {
+    observer.on_diagnostic(&Diagnostic { offset, tid, index, anomaly });
+}
+
+impl FlowObserver for () {}
+
+/// A clone request waiting for its child and/or its reply.
+struct CloneReq {
+    parent: u64,
+    req: TraceEvent,
+    /// Flags with the exit signal, as clone takes them
+    flags: u64,
+    /// Top of the child stack, or 0 to share the stack of the parent
+    stack: u64,
+    tls: u64,
+    fds: FdTable,
+    /// Shared with the parent on CLONE_SIGHAND, otherwise a copy.
+    sighand: Arc<Mutex<SigHand>>,
+    sigmask: u64,
+    /// Return value of clone in the parent, i.e. the child tid.
+    returned: Option<i64>,
+    /// The child which has claimed this request.
+    child: Option<u64>,
+}
+
+impl CloneReq {
+    /// Whether the first reply of a new task can be the child of this
+    /// request, as a guess while clone has not returned.
+    fn matches(&self, child: &TraceHead) -> bool {
+        let parent = &self.req.head;
+        if (self.flags & CLONE_VM != 0) != (child.satp == parent.satp) {
+            return false;
+        }
+        if self.flags & CLONE_SETTLS != 0 && child.tp != self.tls {
+            return false;
+        }
+        if self.stack != 0 && child.usp != self.stack {
+            return false;
+        }
+        true
+    }
+}
+
+/// Flags, top of the child stack and tls of a clone or clone3 request.
+/// clone3 args which are not recorded are taken as all 0.
+fn clone_params(req: &TraceEvent) -> (u64, u64, u64) {
+    let ax = &req.head.ax;
+    if ax[7] != SYS_CLONE3 {
+        return (ax[0], ax[1], ax[3]);
+    }
+    match req.find_payload_io(IN, 0).and_then(|payload| CloneArgs::from_bytes(&payload.data)) {
+        Some(args) => {
+            let stack = if args.stack != 0 { args.stack + args.stack_size } else { 0 };
+            (args.flags | args.exit_signal, stack, args.tls)
+        },
+        None => (0, 0, 0),
+    }
+}
+
+pub struct FlowBuilder<O: FlowObserver = ()> {
+    observer: O,
+    flows: BTreeMap<u64, TraceFlow>,
+    clone_reqs: Vec<CloneReq>,
+    /// New tasks matched by several clone requests, with their parents and
+    /// the offset of their first record, until one of them returns the tid
+    /// which the task tells.
+    unresolved: HashMap<u64, (Vec<u64>, u64)>,
+    task_seq: Vec<u64>,
+    generations: HashMap<u64, u32>,
+    records: usize,
+    /// File offset of the record being pushed
+    offset: u64,
+    /// Accept replies without request
+    partial: bool,
+}
+
+impl Default for FlowBuilder<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlowBuilder<()> {
+    pub fn new() -> Self {
+        Self::with_observer(())
+    }
+}
+
+impl<O: FlowObserver> FlowBuilder<O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self {
+            observer,
+            flows: BTreeMap::new(),
+            clone_reqs: vec![],
+            unresolved: HashMap::new(),
+            task_seq: vec![],
+            generations: HashMap::new(),
+            records: 0,
+            offset: 0,
+            partial: false,
+        }
+    }
+
+    /// Accept traces which start in the middle or have lost records:
+    /// replies without request get placeholders, and their flows are
+    /// marked partial.
+    pub fn set_partial(&mut self, partial: bool) {
+        self.partial = partial;
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Tasks in order of first appearance.
+    pub fn task_seq(&self) -> &[u64] {
+        &self.task_seq
+    }
+
+    /// Number of records pushed.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Feed the next record. Returns the flows of tasks which have exited.
+    pub fn push(&mut self, mut evt: TraceEvent) -> Vec<TraceFlow> {
+        let tid = evt.head.sscratch;
+        evt.seq = self.records;
+        self.records += 1;
+        self.offset = evt.offset;
+        let mut exited = vec![];
+        if self.is_reused(&evt) {
+            let index = self.flows[&tid].events.len();
+            report(&mut self.observer, self.offset, tid, index, Anomaly::TidReused { tid });
+            exited.extend(self.retire(tid, ExitReason::Reused));
+        }
+        if !self.flows.contains_key(&tid) {
+            self.start_flow(&evt);
+        }
+        if evt.is_trap() {
+            self.push_trap(evt);
+            return exited;
+        }
+        let flow = self.flows.get_mut(&tid).unwrap();
+        let tgid = flow.tgid;
+
+        match evt.head.inout {
+            IN => {
+                if let Some(last) = flow.events.last() {
+                    if last.head.inout != OUT {
+                        let (index, sysno) = (flow.events.len() - 1, last.head.ax[7]);
+                        report(&mut self.observer, self.offset, tid, index, Anomaly::Unfinished { tid, sysno });
+                    }
+                }
+
+                match evt.head.ax[7] {
+                    SYS_CLONE | SYS_CLONE3 => {
+                        let (flags, stack, tls) = clone_params(&evt);
+                        let sighand = if flags & CLONE_SIGHAND != 0 {
+                            flow.sighand.clone()
+                        } else {
+                            Arc::new(Mutex::new(flow.sighand.lock().unwrap().clone()))
+                        };
+                        self.clone_reqs.push(CloneReq {
+                            parent: tid,
+                            req: evt.clone(),
+                            flags,
+                            stack,
+                            tls,
+                            fds: flow.fds.clone(),
+                            sighand,
+                            sigmask: flow.sigmask,
+                            returned: None,
+                            child: None,
+                        });
+                        flow.events.push(evt);
+                    },
+                    SYS_RT_SIGRETURN => {
+                        match flow.signal_stack.pop() {
+                            Some(interrupted) => {
+                                if let Some(mask) = flow.saved_sigmask.pop() {
+                                    flow.sigmask = mask;
+                                }
+                                flow.events.push(interrupted);
+                            },
+                            None => {
+                                let index = flow.events.len();
+                                report(&mut self.observer, self.offset, tid, index, Anomaly::NoSignalFrame { tid });
+                                flow.events.push(evt);
+                            },
+                        }
+                    },
+                    SYS_EXIT => {
+                        flow.events.push(evt);
+                        exited.extend(self.retire(tid, ExitReason::Exit));
+                    },
+                    SYS_EXIT_GROUP => {
+                        flow.events.push(evt);
+                        exited.extend(self.retire(tid, ExitReason::ExitGroup));
+                        exited.append(&mut self.kill_group(tgid, tid, ExitReason::Killed { by: tid }));
+                    },
+                    _ => {
+                        flow.events.push(evt);
+                    },
+                }
+            },
+            OUT => {
+                let pending = flow.events.last().is_some_and(|last| last.head.inout == IN);
+                if self.partial && !pending {
+                    // The request is lost, e.g. by wraparound of the tracer.
+                    let index = flow.events.len();
+                    let sysno = evt.head.ax[7];
+                    report(&mut self.observer, self.offset, tid, index, Anomaly::OrphanOut { tid, sysno });
+                    flow.partial = true;
+                    flow.events.push(placeholder(&evt));
+                }
+                let index = flow.events.len().saturating_sub(1);
+                let last = match flow.events.last_mut() {
+                    Some(last) => last,
+                    None => {
+                        let sysno = evt.head.ax[7];
+                        report(&mut self.observer, self.offset, tid, 0, Anomaly::OrphanOut { tid, sysno });
+                        return exited;
+                    },
+                };
+                if evt.head.ax[7] != last.head.ax[7] {
+                    report(&mut self.observer, self.offset, tid, index, Anomaly::Unmatched {
+                        tid,
+                        out_sysno: evt.head.ax[7],
+                        in_sysno: last.head.ax[7],
+                    });
+                }
+
+                // Todo: to distinguish signal by epc is NOT a proper method.
+                // Try to find exact method.
+                // The record of a signal entry has the signal in a0, and the
+                // table only confirms that its handler is at epc.
+                let signum = evt.head.ax[0];
+                let mut sig_entry = flow.sighand.lock().unwrap().is_handler(signum, evt.head.epc as usize);
+                if sig_entry && evt.head.ax[7] == SYS_EXECVE {
+                    // execve starts a new image, so this is its reply.
+                    let epc = evt.head.epc;
+                    report(&mut self.observer, self.offset, tid, index, Anomaly::ExecAtHandler { tid, epc });
+                    sig_entry = false;
+                }
+                if sig_entry {
+                    flow.enter_signal(signum);
+                } else {
+                    last.result = evt.head.ax[0] as i64;
+                    last.payloads.append(&mut evt.payloads);
+                    last.head.inout = OUT;
+                    last.out_head = Some(evt.head);
+                    let clobbers = check_event(last);
+                    if !clobbers.is_empty() {
+                        let sysno = last.head.ax[7];
+                        report(&mut self.observer, self.offset, tid, index, Anomaly::AbiViolation {
+                            tid,
+                            sysno,
+                            clobbers,
+                        });
+                    }
+                    if (-MAX_ERRNO..0).contains(&last.result) && errno_name(last.result) == UNKNOWN_ERRNO {
+                        let (sysno, errno) = (last.head.ax[7], -last.result);
+                        report(&mut self.observer, self.offset, tid, index, Anomaly::UnknownErrno {
+                            tid,
+                            sysno,
+                            errno,
+                        });
+                    }
+                    flow.fds.update(last);
+                    self.observer.on_syscall(tid, last);
+                    let (sysno, result) = (last.head.ax[7], last.result);
+                    if sysno == SYS_RT_SIGACTION && result == 0 && last.head.ax[1] != 0 {
+                        if let Some(sigaction) = parse_sigaction(last, IN) {
+                            flow.sighand.lock().unwrap().set(last.head.ax[0], sigaction);
+                        }
+                    }
+                    if sysno == SYS_RT_SIGPROCMASK {
+                        for anomaly in flow.sigprocmask() {
+                            report(&mut self.observer, self.offset, tid, index, anomaly);
+                        }
+                    }
+                    match sysno {
+                        SYS_CLONE | SYS_CLONE3 if result != 0 => self.clone_returned(tid, result),
+                        SYS_GETTID | SYS_SET_TID_ADDRESS => self.learn_tid(tid, result),
+                        SYS_EXECVE if result == 0 => {
+                            exited.append(&mut self.kill_group(tgid, tid, ExitReason::Dethreaded { by: tid }));
+                            // The execing thread becomes the leader, with
+                            // handlers reset in a private table.
+                            if let Some(flow) = self.flows.get_mut(&tid) {
+                                flow.tgid = tid;
+                                let sighand = flow.sighand.lock().unwrap().exec();
+                                flow.sighand = Arc::new(Mutex::new(sighand));
+                            }
+                        },
+                        _ => (),
+                    }
+                }
+            },
+            _ => unreachable!(),
+        }
+        exited
+    }
+
+    /// Whether the record is the first reply of a new task reusing the tid
+    /// of a live one, i.e. a clone reply of a task not in clone.
+    fn is_reused(&self, evt: &TraceEvent) -> bool {
+        if evt.head.inout != OUT || !is_clone(evt) || evt.head.ax[0] != 0 {
+            return false;
+        }
+        match self.flows.get(&evt.head.sscratch).and_then(|flow| flow.events.last()) {
+            Some(last) => last.head.inout != IN || !is_clone(last),
+            None => false,
+        }
+    }
+
+    /// Remove the flow of an ended task.
+    fn retire(&mut self, tid: u64, reason: ExitReason) -> Option<TraceFlow> {
+        let mut flow = self.flows.remove(&tid)?;
+        self.report_unresolved(tid);
+        flow.exit = Some(reason);
+        // Its clone requests will never be answered.
+        self.clone_reqs.retain(|req| req.parent != tid || req.child.is_some());
+        self.observer.on_task_exit(&flow);
+        Some(flow)
+    }
+
+    /// End the other threads of a thread group.
+    fn kill_group(&mut self, tgid: u64, by: u64, reason: ExitReason) -> Vec<TraceFlow> {
+        let tids = self.flows.values()
+            .filter(|flow| flow.tgid == tgid && flow.tid != by)
+            .map(|flow| flow.tid)
+            .collect::<Vec<_>>();
+        tids.into_iter().filter_map(|tid| self.retire(tid, reason)).collect()
+    }
+
+    /// Place a trap in the timeline. A trap is never pending: its IN is
+    /// taken as done, and its OUT only tells where the task has resumed.
+    fn push_trap(&mut self, mut evt: TraceEvent) {
+        let flow = self.flows.get_mut(&evt.head.sscratch).unwrap();
+        if evt.head.inout == IN {
+            evt.head.inout = OUT;
+            flow.events.push(evt);
+            return;
+        }
+        let entered = match flow.events.last_mut() {
+            Some(last) if last.is_trap() && last.out_head.is_none() && last.head.cause == evt.head.cause => {
+                last.out_head = Some(evt.head.clone());
+                true
+            },
+            _ => false,
+        };
+        if !entered {
+            flow.events.push(evt.clone());
+        }
+        // e.g. SIGSEGV or SIGILL delivered to a handler, with the signal
+        // in a0
+        let signum = evt.head.ax[0];
+        if flow.sighand.lock().unwrap().is_handler(signum, evt.head.epc as usize) {
+            flow.enter_signal(signum);
+        }
+    }
+
+    fn start_flow(&mut self, evt: &TraceEvent) {
+        let tid = evt.head.sscratch;
+        // Start of each event is either req, clone.replay or a trap,
+        // unless the trace starts in the middle.
+        self.task_seq.push(tid);
+        let mut flow = TraceFlow::new();
+        flow.tid = tid;
+        flow.tgid = tid;
+        flow.start = evt.seq;
+        flow.generation = *self.generations.entry(tid)
+            .and_modify(|generation| *generation += 1)
+            .or_insert(0);
+        if evt.head.inout == OUT && !evt.is_trap() && is_clone(evt) {
+            match self.claim_clone(evt) {
+                Some(index) => self.adopt(&mut flow, index),
+                None => {
+                    flow.partial = self.partial && !self.unresolved.contains_key(&tid);
+                    flow.events.push(placeholder(evt));
+                },
+            }
+        } else if evt.head.inout == OUT && !evt.is_trap() && self.partial {
+            // The request is before the start of trace. Outside partial
+            // mode, the reply is reported as an orphan when pushed.
+            flow.partial = true;
+            flow.events.push(placeholder(evt));
+        }
+        self.observer.on_task_start(&flow);
+        self.flows.insert(tid, flow);
+    }
+
+    /// Find the clone request of a new task by its first reply: the only
+    /// one which matches. A task matched by several is left unresolved
+    /// until its own tid, as the task sees it, tells the request.
+    fn claim_clone(&mut self, evt: &TraceEvent) -> Option<usize> {
+        let tid = evt.head.sscratch;
+        let candidates = self.clone_reqs.iter()
+            .enumerate()
+            .filter(|(_, req)| req.child.is_none() && req.matches(&evt.head))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        match candidates.len() {
+            0 => {
+                report(&mut self.observer, self.offset, tid, 0, Anomaly::UnmatchedClone { tid });
+                None
+            },
+            1 => Some(candidates[0]),
+            _ => {
+                let parents = candidates.iter().map(|&i| self.clone_reqs[i].parent).collect();
+                self.unresolved.insert(tid, (parents, self.offset));
+                None
+            },
+        }
+    }
+
+    /// Link a new task to its clone request. A task linked after it has
+    /// started keeps its own state once it has run.
+    fn adopt(&mut self, flow: &mut TraceFlow, index: usize) {
+        let req = &mut self.clone_reqs[index];
+        let flags = req.flags;
+        flow.parent = Some(req.parent);
+        flow.clone_flags = Some(flags);
+        if flow.kernel_tid.is_none() {
+            flow.kernel_tid = req.returned;
+        }
+        if flow.events.len() <= 1 {
+            flow.fds = req.fds.clone();
+            flow.sighand = req.sighand.clone();
+            flow.sigmask = req.sigmask;
+        }
+        // The request replaces the placeholder of a task linked late,
+        // keeping its reply.
+        match flow.events.first_mut() {
+            Some(first) => {
+                let done = std::mem::replace(first, req.req.clone());
+                first.head.inout = done.head.inout;
+                first.out_head = done.out_head;
+                first.result = done.result;
+                first.payloads.extend(done.payloads);
+            },
+            None => flow.events.push(req.req.clone()),
+        }
+        if req.returned.is_some() {
+            self.clone_reqs.remove(index);
+        } else {
+            req.child = Some(flow.tid);
+        }
+        if let Some(parent) = flow.parent.and_then(|p| self.flows.get_mut(&p)) {
+            parent.children.push(flow.tid);
+            if flags & CLONE_THREAD != 0 {
+                flow.tgid = parent.tgid;
+            }
+        }
+    }
+
+    /// The parent has got the reply of clone.
+    fn clone_returned(&mut self, parent: u64, result: i64) {
+        let index = match self.clone_reqs.iter().position(|req| {
+            req.parent == parent && req.returned.is_none()
+        }) {
+            Some(index) => index,
+            None => return,
+        };
+        if result < 0 {
+            self.clone_reqs.remove(index);
+            return;
+        }
+        match self.clone_reqs[index].child {
+            Some(child) => {
+                self.clone_reqs.remove(index);
+                self.learn_tid(child, result);
+            },
+            None => {
+                self.clone_reqs[index].returned = Some(result);
+                // The tid settles a task which several requests have
+                // matched, if the task has already told it.
+                let child = self.unresolved.iter()
+                    .find(|(tid, (parents, _))| {
+                        parents.contains(&parent)
+                            && self.flows.get(tid).is_some_and(|flow| flow.kernel_tid == Some(result))
+                    })
+                    .map(|(&tid, _)| tid);
+                if let Some(child) = child {
+                    self.resolve(child, index);
+                }
+            },
+        }
+    }
+
+    /// Link an unresolved task to the clone request which has returned its
+    /// tid.
+    fn resolve(&mut self, tid: u64, index: usize) {
+        if let Some(mut flow) = self.flows.remove(&tid) {
+            self.unresolved.remove(&tid);
+            self.adopt(&mut flow, index);
+            self.flows.insert(tid, flow);
+        }
+    }
+
+    /// Report a task whose parent has never been settled.
+    fn report_unresolved(&mut self, tid: u64) {
+        if let Some((parents, offset)) = self.unresolved.remove(&tid) {
+            report(&mut self.observer, offset, tid, 0, Anomaly::AmbiguousClone { tid, parents });
+        }
+    }
+
+    /// The task has told its tid, by clone in the parent or by its own
+    /// gettid/set_tid_address.
+    fn learn_tid(&mut self, tid: u64, kernel_tid: i64) {
+        let flow = match self.flows.get_mut(&tid) {
+            Some(flow) => flow,
+            None => return,
+        };
+        match flow.kernel_tid {
+            Some(expected) if expected != kernel_tid => {
+                let index = flow.events.len().saturating_sub(1);
+                report(&mut self.observer, self.offset, tid, index, Anomaly::CloneTidMismatch {
+                    tid,
+                    expected,
+                    actual: kernel_tid,
+                });
+            },
+            Some(_) => (),
+            None => {
+                flow.kernel_tid = Some(kernel_tid);
+                // The tid settles the parent of an unresolved task, if one
+                // of the requests has returned it.
+                let index = self.unresolved.get(&tid).and_then(|(parents, _)| {
+                    self.clone_reqs.iter().position(|req| {
+                        req.child.is_none() && req.returned == Some(kernel_tid) && parents.contains(&req.parent)
+                    })
+                });
+                if let Some(index) = index {
+                    self.resolve(tid, index);
+                }
+            },
+        }
+    }
+
+    /// Flows of tasks which have not exited at the end of trace, by tid.
+    pub fn finish(mut self) -> (Vec<TraceFlow>, O) {
+        let mut unresolved = self.unresolved.keys().copied().collect::<Vec<_>>();
+        unresolved.sort();
+        for tid in unresolved {
+            self.report_unresolved(tid);
+        }
+        let flows = std::mem::take(&mut self.flows).into_values().collect();
+        (flows, self.observer)
+    }
+}
+
+/// All flows of a trace.
+pub struct TraceFlows {
+    /// In order of start
+    pub flows: Vec<TraceFlow>,
+    pub records: usize,
+}
+
+impl TraceFlows {
+    pub fn load(fname: &str, level: usize, partial: bool) -> Result<Self> {
+        let mut builder = FlowBuilder::new();
+        builder.set_partial(partial);
+        let mut flows = vec![];
+        for evt in TraceReader::open(fname, level)? {
+            flows.append(&mut builder.push(evt?));
+        }
+        let records = builder.records();
+        flows.append(&mut builder.finish().0);
+        flows.sort_by_key(|flow| flow.start);
+        Ok(Self { flows, records })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{TracePayload, USER_ECALL};
+    use crate::signal::{SA_RESETHAND, SIGACTION_SIZE};
+
+    const SIGCHLD: u64 = 17;
+
+    /// Kinds of the anomalies, in order.
+    #[derive(Default)]
+    struct Kinds(Vec<&'static str>);
+
+    impl FlowObserver for Kinds {
+        fn on_anomaly(&mut self, anomaly: &Anomaly) {
+            self.0.push(anomaly.kind());
+        }
+    }
+
+    fn rec(inout: u64, tid: u64, sysno: u64, args: &[u64]) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = inout;
+        evt.head.cause = USER_ECALL;
+        evt.head.sscratch = tid;
+        evt.head.epc = if inout == IN { 0x1000 } else { 0x1000 + ECALL_SIZE };
+        evt.head.ax[7] = sysno;
+        evt.head.ax[..args.len()].copy_from_slice(args);
+        evt
+    }
+
+    /// Reply of a request, with the result in a0.
+    fn reply(req: &TraceEvent, result: i64) -> TraceEvent {
+        let mut evt = req.clone();
+        evt.head.inout = OUT;
+        evt.head.epc = req.head.epc + ECALL_SIZE;
+        evt.head.orig_a0 = req.head.ax[0];
+        evt.head.ax[0] = result as u64;
+        evt
+    }
+
+    /// Request and reply of a syscall.
+    fn call(tid: u64, sysno: u64, args: &[u64], result: i64) -> Vec<TraceEvent> {
+        let req = rec(IN, tid, sysno, args);
+        let out = reply(&req, result);
+        vec![req, out]
+    }
+
+    /// rt_sigaction which installs a handler for signum.
+    fn sigaction(tid: u64, signum: u64, handler: usize, flags: usize) -> Vec<TraceEvent> {
+        let mut data = vec![0u8; SIGACTION_SIZE];
+        data[..8].copy_from_slice(&handler.to_ne_bytes());
+        data[8..16].copy_from_slice(&flags.to_ne_bytes());
+        let mut req = rec(IN, tid, SYS_RT_SIGACTION, &[signum, 0x7000, 0, 8]);
+        req.payloads.push(TracePayload { inout: IN, index: 1, data });
+        let out = reply(&req, 0);
+        vec![req, out]
+    }
+
+    /// First reply of the child of a clone request, in its own space.
+    fn child(req: &TraceEvent, tid: u64, satp: u64) -> TraceEvent {
+        let mut evt = reply(req, 0);
+        evt.head.sscratch = tid;
+        evt.head.satp = satp;
+        evt
+    }
+
+    /// Flows in order of start, and kinds of the anomalies.
+    fn build(records: Vec<TraceEvent>, partial: bool) -> (Vec<TraceFlow>, Vec<&'static str>) {
+        let mut builder = FlowBuilder::with_observer(Kinds::default());
+        builder.set_partial(partial);
+        let mut flows = vec![];
+        for evt in records {
+            flows.append(&mut builder.push(evt));
+        }
+        let (rest, kinds) = builder.finish();
+        flows.extend(rest);
+        flows.sort_by_key(|flow| flow.start);
+        (flows, kinds.0)
+    }
+
+    #[test]
+    fn pairs_requests_with_replies() {
+        let mut records = call(0x100, SYS_GETPID, &[], 0x100);
+        records.push(rec(IN, 0x200, SYS_READ, &[0, 0x6000, 10]));
+        records.append(&mut call(0x100, SYS_GETTID, &[], 0x100));
+        records.push(reply(&records[2], 3));
+        let (flows, kinds) = build(records, false);
+        assert!(kinds.is_empty(), "{:?}", kinds);
+        assert_eq!(flows.len(), 2);
+        let results = flows[0].events.iter().map(|evt| (evt.head.ax[7], evt.result)).collect::<Vec<_>>();
+        assert_eq!(results, [(SYS_GETPID, 0x100), (SYS_GETTID, 0x100)]);
+        assert_eq!(flows[0].kernel_tid, Some(0x100));
+        assert_eq!(flows[1].events.len(), 1);
+        assert_eq!(flows[1].events[0].head.inout, OUT);
+        assert_eq!(flows[1].events[0].result, 3);
+    }
+
+    #[test]
+    fn reports_unfinished_and_unmatched() {
+        let records = vec![
+            rec(IN, 0x100, SYS_READ, &[0, 0x6000, 10]),
+            rec(IN, 0x100, SYS_GETPID, &[]),
+            reply(&rec(IN, 0x100, SYS_GETTID, &[]), 0x100),
+        ];
+        let (flows, kinds) = build(records, false);
+        // a7 differs too, as seen by the ABI checker.
+        assert_eq!(kinds, ["unfinished", "unmatched", "abi_violation"]);
+        assert_eq!(flows[0].events.len(), 2);
+    }
+
+    #[test]
+    fn execve_returning_to_handler_is_a_reply() {
+        // The reply looks like the entry of signal 10.
+        let mut records = sigaction(0x100, 10, 0x1000 + ECALL_SIZE as usize, 0);
+        records.append(&mut call(0x100, SYS_EXECVE, &[0x6000, 0, 0], 10));
+        let (flows, kinds) = build(records, false);
+        assert_eq!(kinds, ["exec_at_handler"]);
+        let execve = flows[0].events.last().unwrap();
+        assert_eq!((execve.head.ax[7], execve.head.inout), (SYS_EXECVE, OUT));
+        assert_eq!(execve.signal, SigStage::Empty);
+        assert!(flows[0].signal_stack.is_empty());
+    }
+
+    #[test]
+    fn links_the_only_matching_clone() {
+        let req = rec(IN, 0x100, SYS_CLONE, &[SIGCHLD, 0, 0, 0, 0]);
+        let records = vec![req.clone(), child(&req, 0x300, 2), reply(&req, 7)];
+        let (flows, kinds) = build(records, false);
+        assert!(kinds.is_empty(), "{:?}", kinds);
+        assert_eq!(flows[0].children, [0x300]);
+        assert_eq!(flows[1].parent, Some(0x100));
+        assert_eq!(flows[1].clone_flags, Some(SIGCHLD));
+        assert_eq!(flows[1].kernel_tid, Some(7));
+        assert_eq!(flows[1].events[0].head.inout, OUT);
+    }
+
+    #[test]
+    fn links_ambiguous_clones_by_the_tid_they_see() {
+        let (a, b) = (rec(IN, 0x100, SYS_CLONE, &[SIGCHLD]), rec(IN, 0x200, SYS_CLONE, &[SIGCHLD]));
+        let mut records = vec![a.clone(), b.clone()];
+        records.push(child(&a, 0x301, 2));
+        records.push(child(&b, 0x302, 3));
+        records.push(reply(&b, 8));
+        // One tells its tid after its parent has returned, the other one
+        // before.
+        records.append(&mut call(0x301, SYS_GETTID, &[], 8));
+        records.append(&mut call(0x302, SYS_SET_TID_ADDRESS, &[0x7000], 9));
+        records.push(reply(&a, 9));
+        let (flows, kinds) = build(records, false);
+        assert!(kinds.is_empty(), "{:?}", kinds);
+        let parents = flows.iter().map(|flow| (flow.tid, flow.parent, flow.kernel_tid)).collect::<Vec<_>>();
+        assert_eq!(parents, [
+            (0x100, None, None),
+            (0x200, None, None),
+            (0x301, Some(0x200), Some(8)),
+            (0x302, Some(0x100), Some(9)),
+        ]);
+    }
+
+    #[test]
+    fn reports_unsettled_clones() {
+        let (a, b) = (rec(IN, 0x100, SYS_CLONE, &[SIGCHLD]), rec(IN, 0x200, SYS_CLONE, &[SIGCHLD]));
+        let records = vec![a.clone(), b.clone(), child(&a, 0x301, 2), reply(&a, 8), reply(&b, 9)];
+        let (flows, kinds) = build(records, false);
+        assert_eq!(kinds, ["ambiguous_clone"]);
+        assert_eq!(flows[2].parent, None);
+    }
+
+    #[test]
+    fn reports_mismatched_clones() {
+        let req = rec(IN, 0x100, SYS_CLONE, &[SIGCHLD]);
+        let mut records = vec![req.clone(), child(&req, 0x300, 2), reply(&req, 10)];
+        records.append(&mut call(0x300, SYS_GETTID, &[], 11));
+        // No request matches a task in the space of its parent.
+        records.push(child(&req, 0x301, 0));
+        let (flows, kinds) = build(records, false);
+        assert_eq!(kinds, ["clone_tid_mismatch", "unmatched_clone"]);
+        assert_eq!(flows[1].parent, Some(0x100));
+        assert_eq!(flows[1].kernel_tid, Some(10));
+        assert_eq!(flows[2].parent, None);
+    }
+
+    #[test]
+    fn ends_threads_with_their_group() {
+        let flags = CLONE_VM | CLONE_SIGHAND | CLONE_THREAD;
+        let req = rec(IN, 0x100, SYS_CLONE, &[flags]);
+        let mut records = vec![req.clone(), child(&req, 0x101, 0), reply(&req, 0x101)];
+        let req = rec(IN, 0x101, SYS_CLONE, &[flags]);
+        records.extend([req.clone(), child(&req, 0x102, 0), reply(&req, 0x102)]);
+        records.push(rec(IN, 0x102, SYS_EXIT, &[0]));
+        records.append(&mut call(0x101, SYS_GETPID, &[], 0x100));
+        records.push(rec(IN, 0x100, SYS_EXIT_GROUP, &[0]));
+        let (flows, kinds) = build(records, false);
+        assert!(kinds.is_empty(), "{:?}", kinds);
+        let exits = flows.iter().map(|flow| (flow.tid, flow.tgid, flow.exit)).collect::<Vec<_>>();
+        assert_eq!(exits, [
+            (0x100, 0x100, Some(ExitReason::ExitGroup)),
+            (0x101, 0x100, Some(ExitReason::Killed { by: 0x100 })),
+            (0x102, 0x100, Some(ExitReason::Exit)),
+        ]);
+    }
+
+    #[test]
+    fn execve_ends_other_threads() {
+        let flags = CLONE_VM | CLONE_SIGHAND | CLONE_THREAD;
+        let req = rec(IN, 0x100, SYS_CLONE, &[flags]);
+        let mut records = vec![req.clone(), child(&req, 0x101, 0), reply(&req, 0x101)];
+        records.append(&mut call(0x101, SYS_EXECVE, &[0x6000, 0, 0], 0));
+        records.append(&mut call(0x101, SYS_GETPID, &[], 0x100));
+        let (flows, _) = build(records, false);
+        assert_eq!(flows[0].exit, Some(ExitReason::Dethreaded { by: 0x101 }));
+        assert_eq!((flows[1].exit, flows[1].tgid), (None, 0x101));
+        assert_eq!(flows[1].events.len(), 3);
+    }
+
+    #[test]
+    fn starts_a_new_generation_on_tid_reuse() {
+        let req = rec(IN, 0x100, SYS_CLONE, &[SIGCHLD]);
+        let mut records = vec![req.clone(), child(&req, 0x300, 2), reply(&req, 5)];
+        records.push(rec(IN, 0x300, SYS_EXIT_GROUP, &[0]));
+        // The tid is reused after exit, and then without an exit record.
+        for _ in 0..2 {
+            records.extend([req.clone(), child(&req, 0x300, 2), reply(&req, 5)]);
+        }
+        let (flows, kinds) = build(records, false);
+        assert_eq!(kinds, ["tid_reused"]);
+        let tasks = flows.iter().map(|flow| (flow.name(), flow.parent, flow.exit)).collect::<Vec<_>>();
+        assert_eq!(tasks, [
+            ("0x100".to_string(), None, None),
+            ("0x300".to_string(), Some(0x100), Some(ExitReason::ExitGroup)),
+            ("0x300#1".to_string(), Some(0x100), Some(ExitReason::Reused)),
+            ("0x300#2".to_string(), Some(0x100), None),
+        ]);
+    }
+
+    #[test]
+    fn takes_orphan_replies_only_in_partial_mode() {
+        let mut records = vec![reply(&rec(IN, 0x100, SYS_READ, &[0, 0x6000, 10]), 3)];
+        records.append(&mut call(0x100, SYS_GETPID, &[], 0x100));
+        let (flows, kinds) = build(records.clone(), false);
+        assert_eq!(kinds, ["orphan_out"]);
+        assert!(!flows[0].partial);
+        let sysnos = flows[0].events.iter().map(|evt| evt.head.ax[7]).collect::<Vec<_>>();
+        assert_eq!(sysnos, [SYS_GETPID]);
+
+        // A trace starting in the middle is expected in partial mode.
+        let (flows, kinds) = build(records, true);
+        assert!(kinds.is_empty(), "{:?}", kinds);
+        assert!(flows[0].partial);
+        let results = flows[0].events.iter().map(|evt| (evt.head.ax[7], evt.result)).collect::<Vec<_>>();
+        assert_eq!(results, [(SYS_READ, 3), (SYS_GETPID, 0x100)]);
+    }
+
+    #[test]
+    fn enters_the_signal_in_a0_of_a_shared_handler() {
+        const HANDLER: usize = 0x4444;
+        let mut records = sigaction(0x100, 10, HANDLER, SA_RESETHAND);
+        records.append(&mut sigaction(0x100, 12, HANDLER, SA_RESETHAND));
+        let req = rec(IN, 0x100, SYS_READ, &[0, 0x6000, 10]);
+        let mut entry = reply(&req, 12);
+        entry.head.epc = HANDLER as u64;
+        records.extend([req, entry]);
+        // A reply landing there with another a0 is not a signal.
+        let req = rec(IN, 0x100, SYS_GETPID, &[]);
+        let mut out = reply(&req, 0x100);
+        out.head.epc = HANDLER as u64;
+        records.extend([req, out]);
+        let (flows, _) = build(records, false);
+        let flow = &flows[0];
+        let stages = flow.events.iter().map(|evt| evt.signal.clone()).collect::<Vec<_>>();
+        assert_eq!(stages[2..], [SigStage::Enter(12), SigStage::Empty]);
+        assert_eq!(flow.signal_stack[0].signal, SigStage::Exit(12));
+        assert_eq!(flow.events[3].result, 0x100);
+        let sighand = flow.sighand.lock().unwrap();
+        assert!(sighand.get(10).is_some());
+        assert!(sighand.get(12).is_none());
+    }
+}
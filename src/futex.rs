@@ -0,0 +1,63 @@
+//! futex const
+
+pub const FUTEX_WAIT: u64 = 0;
+pub const FUTEX_WAKE: u64 = 1;
+pub const FUTEX_FD: u64 = 2;
+pub const FUTEX_REQUEUE: u64 = 3;
+pub const FUTEX_CMP_REQUEUE: u64 = 4;
+pub const FUTEX_WAKE_OP: u64 = 5;
+pub const FUTEX_LOCK_PI: u64 = 6;
+pub const FUTEX_UNLOCK_PI: u64 = 7;
+pub const FUTEX_TRYLOCK_PI: u64 = 8;
+pub const FUTEX_WAIT_BITSET: u64 = 9;
+pub const FUTEX_WAKE_BITSET: u64 = 10;
+pub const FUTEX_WAIT_REQUEUE_PI: u64 = 11;
+pub const FUTEX_CMP_REQUEUE_PI: u64 = 12;
+pub const FUTEX_LOCK_PI2: u64 = 13;
+
+pub const FUTEX_PRIVATE_FLAG: u64 = 128;
+pub const FUTEX_CLOCK_REALTIME: u64 = 256;
+const FUTEX_CMD_MASK: u64 = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+
+pub const FUTEX_BITSET_MATCH_ANY: u64 = 0xffffffff;
+
+pub fn futex_cmd(op: u64) -> u64 {
+    op & FUTEX_CMD_MASK
+}
+
+/// generate futex op name, e.g. FUTEX_WAIT_BITSET_PRIVATE|FUTEX_CLOCK_REALTIME
+pub fn futex_op_name(op: u64) -> String {
+    let cmd = match futex_cmd(op) {
+        FUTEX_WAIT => "FUTEX_WAIT".to_string(),
+        FUTEX_WAKE => "FUTEX_WAKE".to_string(),
+        FUTEX_FD => "FUTEX_FD".to_string(),
+        FUTEX_REQUEUE => "FUTEX_REQUEUE".to_string(),
+        FUTEX_CMP_REQUEUE => "FUTEX_CMP_REQUEUE".to_string(),
+        FUTEX_WAKE_OP => "FUTEX_WAKE_OP".to_string(),
+        FUTEX_LOCK_PI => "FUTEX_LOCK_PI".to_string(),
+        FUTEX_UNLOCK_PI => "FUTEX_UNLOCK_PI".to_string(),
+        FUTEX_TRYLOCK_PI => "FUTEX_TRYLOCK_PI".to_string(),
+        FUTEX_WAIT_BITSET => "FUTEX_WAIT_BITSET".to_string(),
+        FUTEX_WAKE_BITSET => "FUTEX_WAKE_BITSET".to_string(),
+        FUTEX_WAIT_REQUEUE_PI => "FUTEX_WAIT_REQUEUE_PI".to_string(),
+        FUTEX_CMP_REQUEUE_PI => "FUTEX_CMP_REQUEUE_PI".to_string(),
+        FUTEX_LOCK_PI2 => "FUTEX_LOCK_PI2".to_string(),
+        other => format!("{:#x}", other),
+    };
+    let mut names = vec![cmd];
+    if op & FUTEX_PRIVATE_FLAG != 0 {
+        names[0].push_str("_PRIVATE");
+    }
+    if op & FUTEX_CLOCK_REALTIME != 0 {
+        names.push("FUTEX_CLOCK_REALTIME".to_string());
+    }
+    names.join("|")
+}
+
+/// Whether arg 3 is a timeout pointer rather than val2.
+pub fn futex_has_timeout(op: u64) -> bool {
+    matches!(
+        futex_cmd(op),
+        FUTEX_WAIT | FUTEX_LOCK_PI | FUTEX_LOCK_PI2 | FUTEX_WAIT_BITSET | FUTEX_WAIT_REQUEUE_PI
+    )
+}
@@ -0,0 +1,127 @@
+//! Syscalls which have not returned at the end of trace.
+//!
+//! When lkmodel hangs, the trace just ends. Each task blocked in a syscall
+//! is listed with the decoded call, the fd it works on, and what the Linux
+//! trace did at the same point if one is given: in the matched task, after
+//! the events aligned with those before the call.
+
+use std::io::{Result, Write};
+use crate::align::{align, EditOp, EventEq};
+use crate::decode::decode_event;
+use crate::event::{SigStage, TraceEvent};
+use crate::flow::TraceFlow;
+use crate::matching::match_tasks;
+use crate::sysno::*;
+use crate::IN;
+
+/// Syscalls whose first argument is a fd.
+const FD_SYSCALLS: &[u64] = &[
+    SYS_READ, SYS_WRITE, SYS_WRITEV, SYS_PREAD64, SYS_IOCTL, SYS_FCNTL,
+    SYS_EPOLL_PWAIT, SYS_ACCEPT, SYS_ACCEPT4, SYS_CONNECT, SYS_SENDTO,
+    SYS_RECVFROM, SYS_MQ_TIMEDSEND, SYS_MQ_TIMEDRECEIVE,
+];
+
+pub struct HungCall<'a> {
+    /// Position of the task in order of start
+    pub task: usize,
+    pub flow: &'a TraceFlow,
+    /// Index of the call in the flow
+    pub index: usize,
+    /// Records since the call started
+    pub age: usize,
+}
+
+impl HungCall<'_> {
+    pub fn event(&self) -> &TraceEvent {
+        &self.flow.events[self.index]
+    }
+
+    /// What the fd argument refers to, if any.
+    pub fn fd(&self) -> Option<String> {
+        let head = &self.event().head;
+        if !FD_SYSCALLS.contains(&head.ax[7]) {
+            return None;
+        }
+        let fd = head.ax[0] as i64;
+        Some(match self.flow.fds.get(fd) {
+            Some(kind) => format!("fd {}: {}", fd, kind),
+            None => format!("fd {}: unknown", fd),
+        })
+    }
+}
+
+/// Calls without reply in flows ordered by start, of a trace of `records`.
+/// Exited tasks are not hung, nor is their exit call, which never returns.
+pub fn hung_calls(flows: &[TraceFlow], records: usize) -> Vec<HungCall<'_>> {
+    flows.iter().enumerate().filter_map(|(task, flow)| {
+        if flow.exit.is_some() {
+            return None;
+        }
+        let last = flow.events.last()?;
+        if last.head.inout != IN || !matches!(last.signal, SigStage::Empty) {
+            return None;
+        }
+        if matches!(last.head.ax[7], SYS_EXIT | SYS_EXIT_GROUP) {
+            return None;
+        }
+        Some(HungCall {
+            task,
+            flow,
+            index: flow.events.len() - 1,
+            age: records - last.seq,
+        })
+    }).collect()
+}
+
+/// Index in a Linux flow of the event at the point of the call: next to
+/// the last one aligned with the events before the call.
+fn linux_index(linux: &TraceFlow, call: &HungCall) -> usize {
+    let ops = align(&linux.events, &call.flow.events[..call.index], &EventEq::default());
+    ops.iter().filter_map(|op| match *op {
+        EditOp::Equal(i, _) | EditOp::Change(i, _) => Some(i + 1),
+        _ => None,
+    }).max().unwrap_or(0)
+}
+
+/// Print the calls of `flows`, along with the Linux flows, both ordered by
+/// start.
+pub fn print_hung<W: Write>(
+    out: &mut W, calls: &[HungCall], flows: &[TraceFlow], linux: Option<&[TraceFlow]>
+) -> Result<()> {
+    let matched = linux.map(|linux| match_tasks(linux, flows));
+    writeln!(out, "Hung syscalls: {}", calls.len())?;
+    for call in calls {
+        writeln!(
+            out,
+            "Task[{}] [{}]: {} started {} records before end",
+            call.flow.name(),
+            call.index,
+            decode_event(call.event()).signature(),
+            call.age
+        )?;
+        if let Some(fd) = call.fd() {
+            writeln!(out, "    {}", fd)?;
+        }
+        let (linux, matched) = match (linux, &matched) {
+            (Some(linux), Some(matched)) => (linux, matched),
+            _ => continue,
+        };
+        match matched.left_of(call.task) {
+            Some(task) => {
+                let flow = &linux[task];
+                let index = linux_index(flow, call);
+                match flow.events.get(index) {
+                    Some(evt) => writeln!(out, "    Linux Task[{}] [{}]: {}", flow.name(), index, evt)?,
+                    None => writeln!(
+                        out,
+                        "    Linux Task[{}] ended after {} syscalls",
+                        flow.name(),
+                        flow.events.len()
+                    )?,
+                }
+            },
+            None => writeln!(out, "    Linux: no matched task")?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! pipe/eventfd/memfd and System V/POSIX IPC const.
+
+/// open flags, shared by pipe2 and mq_open
+pub const O_ACCMODE: u64 = 0o3;
+pub const O_RDONLY: u64 = 0o0;
+pub const O_WRONLY: u64 = 0o1;
+pub const O_RDWR: u64 = 0o2;
+pub const O_CREAT: u64 = 0o100;
+pub const O_EXCL: u64 = 0o200;
+pub const O_NONBLOCK: u64 = 0o4000;
+pub const O_DIRECT: u64 = 0o40000;
+pub const O_CLOEXEC: u64 = 0o2000000;
+
+/// eventfd flags
+pub const EFD_SEMAPHORE: u64 = 1;
+pub const EFD_NONBLOCK: u64 = O_NONBLOCK;
+pub const EFD_CLOEXEC: u64 = O_CLOEXEC;
+
+/// memfd_create flags
+pub const MFD_CLOEXEC: u64 = 0x1;
+pub const MFD_ALLOW_SEALING: u64 = 0x2;
+pub const MFD_HUGETLB: u64 = 0x4;
+
+/// System V IPC
+pub const IPC_PRIVATE: u64 = 0;
+pub const IPC_CREAT: u64 = 0o1000;
+pub const IPC_EXCL: u64 = 0o2000;
+pub const IPC_NOWAIT: u64 = 0o4000;
+
+pub const IPC_RMID: u64 = 0;
+pub const IPC_SET: u64 = 1;
+pub const IPC_STAT: u64 = 2;
+pub const IPC_INFO: u64 = 3;
+
+pub const SHM_RDONLY: u64 = 0o10000;
+pub const SHM_RND: u64 = 0o20000;
+pub const SHM_REMAP: u64 = 0o40000;
+pub const SHM_EXEC: u64 = 0o100000;
+
+pub const SEM_UNDO: u64 = 0x1000;
+
+pub const MSG_NOERROR: u64 = 0o10000;
+pub const MSG_EXCEPT: u64 = 0o20000;
+pub const MSG_COPY: u64 = 0o40000;
+
+/// struct sembuf { unsigned short sem_num; short sem_op; short sem_flg; }
+const SEMBUF_SIZE: usize = 6;
+
+fn flag_names(flags: u64, table: &[(u64, &str)]) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    let mut rest = flags;
+    for (bit, name) in table {
+        if flags & bit != 0 {
+            names.push(name.to_string());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#o}", rest));
+    }
+    names
+}
+
+fn join_or_zero(names: Vec<String>) -> String {
+    if !names.is_empty() {
+        names.join("|")
+    } else {
+        String::from("0")
+    }
+}
+
+pub fn pipe_flag_name(flags: u64) -> String {
+    join_or_zero(flag_names(flags, &[
+        (O_CLOEXEC, "O_CLOEXEC"),
+        (O_NONBLOCK, "O_NONBLOCK"),
+        (O_DIRECT, "O_DIRECT"),
+    ]))
+}
+
+pub fn eventfd_flag_name(flags: u64) -> String {
+    join_or_zero(flag_names(flags, &[
+        (EFD_SEMAPHORE, "EFD_SEMAPHORE"),
+        (EFD_NONBLOCK, "EFD_NONBLOCK"),
+        (EFD_CLOEXEC, "EFD_CLOEXEC"),
+    ]))
+}
+
+pub fn memfd_flag_name(flags: u64) -> String {
+    join_or_zero(flag_names(flags, &[
+        (MFD_CLOEXEC, "MFD_CLOEXEC"),
+        (MFD_ALLOW_SEALING, "MFD_ALLOW_SEALING"),
+        (MFD_HUGETLB, "MFD_HUGETLB"),
+    ]))
+}
+
+/// open flags for mq_open
+pub fn oflag_name(flags: u64) -> String {
+    let mut names = vec![match flags & O_ACCMODE {
+        O_RDONLY => "O_RDONLY".to_string(),
+        O_WRONLY => "O_WRONLY".to_string(),
+        O_RDWR => "O_RDWR".to_string(),
+        other => format!("{:#o}", other),
+    }];
+    names.append(&mut flag_names(flags & !O_ACCMODE, &[
+        (O_CREAT, "O_CREAT"),
+        (O_EXCL, "O_EXCL"),
+        (O_NONBLOCK, "O_NONBLOCK"),
+        (O_CLOEXEC, "O_CLOEXEC"),
+    ]));
+    names.join("|")
+}
+
+pub fn ipc_key_name(key: u64) -> String {
+    if key == IPC_PRIVATE {
+        String::from("IPC_PRIVATE")
+    } else {
+        format!("{:#x}", key as u32)
+    }
+}
+
+/// shmget/semget/msgget flags: IPC_* plus permission bits
+pub fn ipc_get_flag_name(flags: u64) -> String {
+    let mut names = flag_names(flags & !0o777, &[
+        (IPC_CREAT, "IPC_CREAT"),
+        (IPC_EXCL, "IPC_EXCL"),
+    ]);
+    names.push(format!("{:#o}", flags & 0o777));
+    names.join("|")
+}
+
+pub fn ipc_cmd_name(cmd: u64) -> String {
+    match cmd {
+        IPC_RMID => "IPC_RMID".to_string(),
+        IPC_SET => "IPC_SET".to_string(),
+        IPC_STAT => "IPC_STAT".to_string(),
+        IPC_INFO => "IPC_INFO".to_string(),
+        _ => format!("{:#x}", cmd),
+    }
+}
+
+pub fn shmat_flag_name(flags: u64) -> String {
+    join_or_zero(flag_names(flags, &[
+        (SHM_RDONLY, "SHM_RDONLY"),
+        (SHM_RND, "SHM_RND"),
+        (SHM_REMAP, "SHM_REMAP"),
+        (SHM_EXEC, "SHM_EXEC"),
+    ]))
+}
+
+pub fn ipc_msg_flag_name(flags: u64) -> String {
+    join_or_zero(flag_names(flags, &[
+        (IPC_NOWAIT, "IPC_NOWAIT"),
+        (MSG_NOERROR, "MSG_NOERROR"),
+        (MSG_EXCEPT, "MSG_EXCEPT"),
+        (MSG_COPY, "MSG_COPY"),
+    ]))
+}
+
+/// Array of struct sembuf.
+pub fn sembufs_name(data: &[u8]) -> String {
+    let sops = data.chunks_exact(SEMBUF_SIZE).map(|chunk| {
+        let num = u16::from_ne_bytes([chunk[0], chunk[1]]);
+        let op = i16::from_ne_bytes([chunk[2], chunk[3]]);
+        let flg = i16::from_ne_bytes([chunk[4], chunk[5]]) as u16 as u64;
+        let flg = join_or_zero(flag_names(flg, &[
+            (IPC_NOWAIT, "IPC_NOWAIT"),
+            (SEM_UNDO, "SEM_UNDO"),
+        ]));
+        format!("{{{}, {}, {}}}", num, op, flg)
+    }).collect::<Vec<_>>();
+    format!("[{}]", sops.join(", "))
+}
+
+/// struct msgbuf { long mtype; char mtext[]; }
+pub fn msgbuf_name(data: &[u8]) -> String {
+    if data.len() < 8 {
+        return format!("{:?}", data);
+    }
+    let mut buf = [0u8; 8];
+    buf.clone_from_slice(&data[..8]);
+    let mtype = i64::from_ne_bytes(buf);
+    format!("{{mtype={}, mtext={:?}}}", mtype, String::from_utf8_lossy(&data[8..]))
+}
+
+/// struct mq_attr { long mq_flags, mq_maxmsg, mq_msgsize, mq_curmsgs; }
+pub fn mq_attr_name(data: &[u8]) -> String {
+    if data.len() < 32 {
+        return format!("{:?}", data);
+    }
+    let fields = data[..32].chunks_exact(8).map(|chunk| {
+        let mut buf = [0u8; 8];
+        buf.clone_from_slice(chunk);
+        i64::from_ne_bytes(buf)
+    }).collect::<Vec<_>>();
+    let flags = if fields[0] as u64 & O_NONBLOCK != 0 { "O_NONBLOCK" } else { "0" };
+    format!(
+        "{{mq_flags={}, mq_maxmsg={}, mq_msgsize={}, mq_curmsgs={}}}",
+        flags, fields[1], fields[2], fields[3]
+    )
+}
@@ -1,10 +1,31 @@
+pub mod abi;
+pub mod align;
+pub mod decode;
+pub mod divergence;
+pub mod equiv;
 mod errno;
 pub mod event;
+pub mod fd;
+pub mod flow;
+mod futex;
+pub mod hung;
+mod ipc;
+pub mod matching;
+pub mod matrix;
 mod payload;
+mod poll;
 mod mmap;
+pub mod profile;
+pub mod report;
+pub mod sched;
 #[allow(unused)]
 pub mod sysno;
-mod signal;
+pub mod signal;
+mod socket;
+pub mod stat;
+pub mod trap;
+pub mod unimpl;
+pub mod waiver;
 
 pub const IN: u64 = 0;
 pub const OUT: u64 = 1;
@@ -1,153 +1,271 @@
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Result;
-use std::collections::{BTreeMap, HashSet};
-use lkdiff::sysno::*;
-use lkdiff::event::{TraceEvent, TraceFlow, USER_ECALL};
-use lkdiff::event::parse_sigaction;
-use lkdiff::event::SigStage;
-use lkdiff::event::{print_events, LK_MAGIC, TE_SIZE, parse_event};
-use lkdiff::{IN, OUT};
+use std::io::{self, Result};
+use std::process;
+use lkdiff::divergence::print_summary;
+use lkdiff::event::TraceReader;
+use lkdiff::flow::{Diagnostic, FlowBuilder, FlowObserver, TraceFlows};
+use lkdiff::hung::{hung_calls, print_hung};
+use lkdiff::matching::print_match;
+use lkdiff::matrix::Matrix;
+use lkdiff::profile::{set_profile, Learner, Profile};
+use lkdiff::report::{DiffResult, DiffStatus};
+use lkdiff::unimpl::{print_unimplemented, unimplemented};
+use lkdiff::waiver::{load_waivers, Waiver};
 
-fn main() {
-    let rfile;
-    let mut level = 1;
+const USAGE: &str = "Usage: lkdiff [-2] [--strict] [--traps] [--partial] [--profile file] trace.data
+       lkdiff hung [-2] [--partial] lkmodel.data [linux.data]
+       lkdiff diff [-2] [--partial] [--traps] [--json] [--waivers file] [--profile file] linux.data lkmodel.data
+       lkdiff unimplemented [-2] [--partial] [--traps] lkmodel.data [linux.data]
+       lkdiff matrix [-2] [--partial] [--traps] [--csv] [--waivers file] [--profile file] app_dir
+       lkdiff learn [-2] [--partial] linux.data linux.data...";
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() == 2 {
-        rfile = &args[1];
-    } else if args.len() == 3{
-        rfile = &args[2];
-        if args[1] == "-2" {
-            level = 2;
-        }
-    } else {
-        println!("Usage: lkdiff [-2] trace.data");
-        return;
-    }
-    parse_file(rfile, level).expect("reference is a bad file.");
+#[derive(Default)]
+struct Options {
+    level: usize,
+    /// Diagnostics are errors
+    strict: bool,
+    /// Print and diff traps other than ecall
+    traps: bool,
+    /// The trace may start in the middle
+    partial: bool,
+    /// Export the matrix as CSV rather than Markdown
+    csv: bool,
+    /// Write the diff as JSON
+    json: bool,
+    waivers: Vec<Waiver>,
 }
 
-fn parse_file(fname: &str, level: usize) -> Result<()> {
-    let f = File::open(fname)?;
-    let mut filesize = f.metadata().unwrap().len() as usize;
-    let mut reader = BufReader::new(f);
-
-    let mut sighand_set: HashSet<usize> = HashSet::new();
-    let mut events_map: BTreeMap<u64, TraceFlow> = BTreeMap::new();
-    let mut vfork_req: Vec<TraceEvent> = vec![];
-    let mut task_seq: Vec<u64> = vec![];
-    while filesize >= TE_SIZE {
-        let mut evt = parse_event(&mut reader, level)?;
-        let advance = evt.head.totalsize as usize;
-        assert_eq!(evt.head.magic, LK_MAGIC);
-        assert_eq!(evt.head.headsize, TE_SIZE as u16);
-        assert!(evt.head.totalsize >= evt.head.headsize as u32);
-
-        /*
-        println!("tid: {:#x} -> ({})[{:#x}, {:#x}, {}]; pid: {:#x}",
-            evt.head.sscratch, evt.head.inout, evt.head.cause,
-            evt.head.epc, evt.head.ax[7], evt.head.satp);
-            */
-
-        assert_eq!(evt.head.cause, USER_ECALL);
-
-        let tid = evt.head.sscratch;
-        let flow = match events_map.get_mut(&tid) {
-            Some(q) => q,
-            None => {
-                // Start of each event is either req or clone.replay
-                assert!(evt.head.inout == IN || evt.head.ax[7] == SYS_CLONE);
-                task_seq.push(tid);
-                //println!("New events: {:#x}", tid);
-                events_map.insert(tid, TraceFlow::new());
-                let flow = events_map.get_mut(&tid).unwrap();
-                if evt.head.inout == OUT {
-                    let req = vfork_req.pop().unwrap();
-                    flow.events.push(req);
-                }
-                flow
-            },
-        };
-
-        match evt.head.inout {
-            IN => {
-                //println!("request: {}", evt.head.ax[7]);
-                if let Some(last) = flow.events.last() {
-                    if last.head.inout != OUT {
-                        println!("======================= might be killed: {}", last.head.ax[7]);
-                    }
-                }
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let cmd = match args.first().map(String::as_str) {
+        Some("hung") | Some("diff") | Some("unimplemented") | Some("matrix") | Some("learn") => {
+            Some(args.remove(0))
+        },
+        _ => None,
+    };
+    let mut opts = Options { level: 1, ..Default::default() };
+    let (waivers, profile) = match (take_value(&mut args, "--waivers"), take_value(&mut args, "--profile")) {
+        (Ok(waivers), Ok(profile)) => (waivers, profile),
+        _ => {
+            println!("{}", USAGE);
+            return;
+        },
+    };
+    // A bad file fails a pipeline with status 2, as divergences do.
+    if let Some(fname) = waivers {
+        opts.waivers = load_waivers(&fname).unwrap_or_else(|err| {
+            eprintln!("bad waiver file {}: {}", fname, err);
+            process::exit(2);
+        });
+    }
+    if let Some(fname) = profile {
+        set_profile(Profile::load(&fname).unwrap_or_else(|err| {
+            eprintln!("bad profile {}: {}", fname, err);
+            process::exit(2);
+        }));
+    }
+    args.retain(|arg| match arg.as_str() {
+        "-2" => {
+            opts.level = 2;
+            false
+        },
+        "--strict" => {
+            opts.strict = true;
+            false
+        },
+        "--traps" => {
+            opts.traps = true;
+            false
+        },
+        "--partial" => {
+            opts.partial = true;
+            false
+        },
+        "--csv" => {
+            opts.csv = true;
+            false
+        },
+        "--json" => {
+            opts.json = true;
+            false
+        },
+        _ => true,
+    });
 
-                let sysno = evt.head.ax[7];
-                match sysno {
-                    SYS_CLONE => {
-                        vfork_req.push(evt.clone());
-                        flow.events.push(evt);
-                    },
-                    SYS_RT_SIGRETURN => {
-                        //println!("signal exit: ");
-                        flow.events.push(flow.signal_stack.pop().unwrap());
-                    },
-                    SYS_EXIT_GROUP => {
-                        flow.events.push(evt);
-                        print_events(tid, &flow.events);
-                        events_map.remove(&tid);
-                    },
-                    _ => {
-                        flow.events.push(evt);
-                    },
-                }
-            },
-            OUT => {
-                let last = flow.events.last_mut().expect("No requests in event queue!");
-                //assert_eq!(evt.head.ax[7], last.head.ax[7], "{:#x} != {:#x}", evt.head.ax[7], last.head.ax[7]);
-                if evt.head.ax[7] != last.head.ax[7] {
-                    println!("======================= unmatch: {} != {}", evt.head.ax[7], last.head.ax[7]);
-                }
+    match (cmd.as_deref(), args.len()) {
+        (None, 1) => {
+            let diags = parse_file(&args[0], &opts).expect("reference is a bad file.");
+            if opts.strict && diags != 0 {
+                process::exit(1);
+            }
+        },
+        (Some("hung"), 1) | (Some("hung"), 2) => {
+            report_hung(&args[0], args.get(1), &opts).expect("reference is a bad file.")
+        },
+        (Some("unimplemented"), 1) | (Some("unimplemented"), 2) => {
+            report_unimplemented(&args[0], args.get(1), &opts).expect("reference is a bad file.")
+        },
+        (Some("matrix"), 1) => {
+            let matrix = Matrix::load(&args[0], opts.level, opts.partial, &opts.waivers, opts.traps)
+                .expect("bad app directory.");
+            if opts.csv {
+                print!("{}", matrix.to_csv());
+            } else {
+                print!("{}", matrix.to_markdown());
+            }
+        },
+        (Some("learn"), n) if n >= 2 => {
+            learn_profile(&args, &opts).expect("reference is a bad file.")
+        },
+        (Some("diff"), 2) => {
+            let status = diff_files(&args[0], &args[1], &opts).expect("reference is a bad file.");
+            process::exit(status.exit_code());
+        },
+        _ => println!("{}", USAGE),
+    }
+}
 
-                if evt.head.ax[7] == SYS_RT_SIGACTION {
-                    if let Some((sigaction, _)) = parse_sigaction(&evt) {
-                        //println!("============ sigaction.handler {:#x}", sigaction.handler);
-                        sighand_set.insert(sigaction.handler);
-                    }
-                }
+/// Diagnostics go to stderr, apart from the events to diff.
+struct Reporter {
+    strict: bool,
+    counts: BTreeMap<&'static str, usize>,
+}
 
-                // Todo: to distinguish signal by epc is NOT a proper method.
-                // Try to find exact method.
-                if sighand_set.contains(&(evt.head.epc as usize)) {
-                    assert!(evt.head.ax[7] != SYS_EXECVE);
-                    let mut last = flow.events.pop().unwrap();
-                    last.signal = SigStage::Exit(evt.head.ax[0]);
-                    flow.signal_stack.push(last);
+impl FlowObserver for Reporter {
+    fn on_diagnostic(&mut self, diag: &Diagnostic) {
+        let severity = if self.strict { "error" } else { "warning" };
+        eprintln!("{}: {}", severity, diag);
+        *self.counts.entry(diag.anomaly.kind()).or_insert(0) += 1;
+    }
+}
 
-                    //println!("signal enter: {}", evt.head.ax[0]);
-                    let mut sig_req = TraceEvent::default();
-                    sig_req.signal = SigStage::Enter(evt.head.ax[0]);
-                    sig_req.head.inout = OUT;
-                    sig_req.head.ax[0] = evt.head.ax[0];
-                    flow.events.push(sig_req);
-                } else {
-                    //println!("event out: {}", evt.head.ax[7]);
-                    last.result = evt.head.ax[0] as i64;
-                    last.payloads.append(&mut evt.payloads);
-                    last.head.inout = OUT;
-                }
-                //println!("replay: {}", last);
-            },
-            _ => unreachable!(),
+impl Reporter {
+    /// Print counts by kind, returning the total.
+    fn summary(&self) -> usize {
+        let total = self.counts.values().sum();
+        if total != 0 {
+            let counts = self.counts.iter()
+                .map(|(kind, count)| format!("{}: {}", kind, count))
+                .collect::<Vec<_>>();
+            eprintln!("Diagnostics: {} ({})", total, counts.join(", "));
         }
+        total
+    }
+}
 
-        filesize -= advance;
+/// Print the flows of a trace, returning the number of diagnostics.
+fn parse_file(fname: &str, opts: &Options) -> Result<usize> {
+    let reporter = Reporter { strict: opts.strict, counts: BTreeMap::new() };
+    let mut builder = FlowBuilder::with_observer(reporter);
+    builder.set_partial(opts.partial);
+    for evt in TraceReader::open(fname, opts.level)? {
+        for flow in builder.push(evt?) {
+            flow.print(opts.traps);
+        }
     }
 
-    for (id, flow) in events_map.iter() {
-        print_events(*id, &flow.events);
+    let task_seq = builder.task_seq().to_vec();
+    let records = builder.records();
+    let (mut flows, reporter) = builder.finish();
+    for flow in &flows {
+        flow.print(opts.traps);
     }
     println!("Task sequence: ");
     for tid in task_seq {
         println!("{:#x}", tid);
     }
+
+    flows.sort_by_key(|flow| flow.start);
+    // Not in the events to diff.
+    let calls = hung_calls(&flows, records);
+    if !calls.is_empty() {
+        print_hung(&mut io::stderr(), &calls, &flows, None)?;
+    }
+    Ok(reporter.summary())
+}
+
+fn report_hung(fname: &str, linux: Option<&String>, opts: &Options) -> Result<()> {
+    let lkmodel = TraceFlows::load(fname, opts.level, opts.partial)?;
+    let linux = match linux {
+        Some(linux) => Some(TraceFlows::load(linux, opts.level, opts.partial)?),
+        None => None,
+    };
+    let calls = hung_calls(&lkmodel.flows, lkmodel.records);
+    print_hung(&mut io::stdout(), &calls, &lkmodel.flows, linux.as_ref().map(|linux| linux.flows.as_slice()))
+}
+
+fn report_unimplemented(fname: &str, linux: Option<&String>, opts: &Options) -> Result<()> {
+    let lkmodel = TraceFlows::load(fname, opts.level, opts.partial)?;
+    let linux = match linux {
+        Some(linux) => Some(TraceFlows::load(linux, opts.level, opts.partial)?),
+        None => None,
+    };
+    let found = unimplemented(&lkmodel.flows, linux.as_ref().map(|linux| linux.flows.as_slice()), opts.traps);
+    print_unimplemented(&found);
+    Ok(())
+}
+
+/// Remove `flag value` from the args, returning the value; an error if the
+/// value is missing.
+fn take_value(args: &mut Vec<String>, flag: &str) -> std::result::Result<Option<String>, ()> {
+    let i = match args.iter().position(|arg| arg == flag) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    if i + 1 == args.len() {
+        return Err(());
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+/// Print the fields which vary between the first run and the others.
+fn learn_profile(fnames: &[String], opts: &Options) -> Result<()> {
+    let base = TraceFlows::load(&fnames[0], opts.level, opts.partial)?;
+    let mut learner = Learner::default();
+    for fname in &fnames[1..] {
+        let run = TraceFlows::load(fname, opts.level, opts.partial)?;
+        learner.compare(&base.flows, &run.flows);
+    }
+    print!("{}", learner.report());
     Ok(())
 }
+
+/// Print the diff, or write it as JSON, returning the status.
+fn diff_files(linux_path: &str, lkmodel_path: &str, opts: &Options) -> Result<DiffStatus> {
+    let linux = TraceFlows::load(linux_path, opts.level, opts.partial)?;
+    let lkmodel = TraceFlows::load(lkmodel_path, opts.level, opts.partial)?;
+    let mut result = DiffResult::new(&linux.flows, &lkmodel.flows, opts.traps);
+    result.apply_waivers(&opts.waivers);
+    if opts.json {
+        print!("{}", result.to_json(&linux.flows, &lkmodel.flows, linux_path, lkmodel_path));
+    } else {
+        print_match(&result.matched, &linux.flows, &lkmodel.flows);
+        for div in &result.divergences {
+            println!("{}", div);
+        }
+        print_summary(&result.divergences);
+        if !result.waived.is_empty() {
+            println!("Waived: {}", result.waived.len());
+            for waived in &result.waived {
+                let waiver = &waived.waiver;
+                println!("[{}] {}", waiver.id, waived.divergence);
+                if !waiver.reason.is_empty() {
+                    println!("    reason: {}", waiver.reason);
+                }
+                if !waiver.ticket.is_empty() {
+                    println!("    ticket: {}", waiver.ticket);
+                }
+            }
+        }
+        if !result.unused_waivers.is_empty() {
+            println!("Unused waivers: {}", result.unused_waivers.len());
+            for waiver in &result.unused_waivers {
+                println!("[{}] line {}", waiver.id, waiver.line);
+            }
+        }
+    }
+    Ok(result.status())
+}
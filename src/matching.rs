@@ -0,0 +1,310 @@
+//! Pairing of tasks between two traces.
+//!
+//! Tids, satp and the order of exit differ between kernels, so tasks are
+//! paired by their position in the process tree instead: roots with roots,
+//! then the children of paired tasks in order of clone. Among siblings, a
+//! pair is scored by the exec images, the clone order and the similarity of
+//! the syscall sequences. Tasks left without a pair are listed as such.
+
+use crate::align::{shortest_edit, EditOp, EventEq, ResultCmp};
+use crate::decode::{decode_event, SysResult};
+use crate::flow::TraceFlow;
+use crate::sysno::SYS_EXECVE;
+
+/// Score of the same exec images; different images cost as much.
+const EXEC_SCORE: f64 = 1.0;
+/// Score of the same position among siblings.
+const POSITION_SCORE: f64 = 0.5;
+/// Least score of a pair.
+const MIN_SCORE: f64 = 0.5;
+
+/// Tasks paired by index in the flows of each trace, ordered by start.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaskMatch {
+    /// (left, right, similarity of the syscall sequences)
+    pub pairs: Vec<(usize, usize, f64)>,
+    pub left_only: Vec<usize>,
+    pub right_only: Vec<usize>,
+}
+
+impl TaskMatch {
+    /// The right task paired with a left one.
+    pub fn right_of(&self, left: usize) -> Option<usize> {
+        self.pairs.iter().find(|pair| pair.0 == left).map(|pair| pair.1)
+    }
+
+    /// The left task paired with a right one.
+    pub fn left_of(&self, right: usize) -> Option<usize> {
+        self.pairs.iter().find(|pair| pair.1 == right).map(|pair| pair.0)
+    }
+}
+
+/// Shape of a trace: parent and children of each flow, and exec images.
+struct Tree {
+    roots: Vec<usize>,
+    /// In order of start, which is the order of clone
+    children: Vec<Vec<usize>>,
+    images: Vec<Vec<String>>,
+    names: Vec<Vec<String>>,
+}
+
+impl Tree {
+    /// `flows` are ordered by start.
+    fn new(flows: &[TraceFlow]) -> Self {
+        let mut roots = vec![];
+        let mut children = vec![vec![]; flows.len()];
+        for i in 0..flows.len() {
+            match parent_of(flows, i) {
+                Some(parent) => children[parent].push(i),
+                None => roots.push(i),
+            }
+        }
+        let eq = EventEq { args: false, result: ResultCmp::Ignore, equiv: true };
+        let names = flows.iter().map(|flow| {
+            flow.events.iter().map(|evt| eq.key(evt).name).collect()
+        }).collect();
+        Self {
+            roots,
+            children,
+            images: flows.iter().map(exec_images).collect(),
+            names,
+        }
+    }
+}
+
+/// The parent flow is the latest one of its tid started before the child.
+fn parent_of(flows: &[TraceFlow], child: usize) -> Option<usize> {
+    let tid = flows[child].parent?;
+    flows[..child].iter().rposition(|flow| flow.tid == tid)
+}
+
+/// Position of each flow in the process tree: `0` for the first root,
+/// `0.1` for its second child, and so on. `flows` are ordered by start.
+pub fn task_paths(flows: &[TraceFlow]) -> Vec<String> {
+    let mut paths: Vec<String> = vec![];
+    let mut roots = 0;
+    let mut children = vec![0; flows.len()];
+    for i in 0..flows.len() {
+        let path = match parent_of(flows, i) {
+            Some(parent) => {
+                children[parent] += 1;
+                format!("{}.{}", paths[parent], children[parent] - 1)
+            },
+            None => {
+                roots += 1;
+                (roots - 1).to_string()
+            },
+        };
+        paths.push(path);
+    }
+    paths
+}
+
+/// Path and argv of each successful execve of the flow.
+pub fn exec_images(flow: &TraceFlow) -> Vec<String> {
+    flow.events.iter().filter(|evt| {
+        evt.head.ax[7] == SYS_EXECVE && !evt.is_trap() && evt.out_head.is_some()
+    }).filter_map(|evt| {
+        let call = decode_event(evt);
+        match call.result {
+            SysResult::Ok { .. } => Some(format!("{} {}", call.args[0].value, call.args[1].value)),
+            SysResult::Err { .. } => None,
+        }
+    }).collect()
+}
+
+/// Similarity of two sequences of syscall names, from 0 to 1.
+pub fn similarity(left: &[String], right: &[String]) -> f64 {
+    if left.is_empty() && right.is_empty() {
+        return 1.0;
+    }
+    let equal = shortest_edit(left, right).iter()
+        .filter(|op| matches!(op, EditOp::Equal(..)))
+        .count();
+    2.0 * equal as f64 / (left.len() + right.len()) as f64
+}
+
+/// Pair the tasks of two traces, each with flows ordered by start.
+pub fn match_tasks(left: &[TraceFlow], right: &[TraceFlow]) -> TaskMatch {
+    let left_tree = Tree::new(left);
+    let right_tree = Tree::new(right);
+    let mut pairs = vec![];
+    let mut siblings = vec![(left_tree.roots.clone(), right_tree.roots.clone())];
+    while let Some((lefts, rights)) = siblings.pop() {
+        for (l, r, sim) in match_siblings(&left_tree, &right_tree, &lefts, &rights) {
+            pairs.push((l, r, sim));
+            siblings.push((left_tree.children[l].clone(), right_tree.children[r].clone()));
+        }
+    }
+    pairs.sort_by_key(|pair| pair.0);
+    let left_only = (0..left.len()).filter(|&l| pairs.iter().all(|pair| pair.0 != l)).collect();
+    let right_only = (0..right.len()).filter(|&r| pairs.iter().all(|pair| pair.1 != r)).collect();
+    TaskMatch { pairs, left_only, right_only }
+}
+
+/// Pair siblings by the best scores first.
+fn match_siblings(
+    left_tree: &Tree, right_tree: &Tree, lefts: &[usize], rights: &[usize]
+) -> Vec<(usize, usize, f64)> {
+    let mut scored = vec![];
+    for (i, &l) in lefts.iter().enumerate() {
+        for (j, &r) in rights.iter().enumerate() {
+            let sim = similarity(&left_tree.names[l], &right_tree.names[r]);
+            let mut score = sim;
+            let (left_images, right_images) = (&left_tree.images[l], &right_tree.images[r]);
+            if left_images == right_images {
+                if !left_images.is_empty() {
+                    score += EXEC_SCORE;
+                }
+            } else if !left_images.is_empty() && !right_images.is_empty() {
+                score -= EXEC_SCORE;
+            }
+            if i == j {
+                score += POSITION_SCORE;
+            }
+            if score >= MIN_SCORE {
+                scored.push((score, i, j, sim));
+            }
+        }
+    }
+    // Stable, so ties go by clone order.
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut left_used = vec![false; lefts.len()];
+    let mut right_used = vec![false; rights.len()];
+    let mut pairs = vec![];
+    for (_, i, j, sim) in scored {
+        if !left_used[i] && !right_used[j] {
+            left_used[i] = true;
+            right_used[j] = true;
+            pairs.push((lefts[i], rights[j], sim));
+        }
+    }
+    pairs
+}
+
+/// Print the pairs and the tasks only in either trace, Linux on the left.
+pub fn print_match(matched: &TaskMatch, left: &[TraceFlow], right: &[TraceFlow]) {
+    println!("Matched tasks: {}", matched.pairs.len());
+    for &(l, r, sim) in &matched.pairs {
+        println!("Task[{}] <-> Task[{}] similarity {:.2}", left[l].name(), right[r].name(), sim);
+    }
+    for (side, only, flows) in [("Linux", &matched.left_only, left), ("lkmodel", &matched.right_only, right)] {
+        if only.is_empty() {
+            continue;
+        }
+        println!("Only in {}: {}", side, only.len());
+        for &i in only {
+            let flow = &flows[i];
+            match exec_images(flow).last() {
+                Some(image) => println!("Task[{}] {}", flow.name(), image),
+                None => println!("Task[{}]", flow.name()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{TraceEvent, TracePayload, USER_ECALL};
+    use crate::sysno::*;
+    use crate::{IN, OUT};
+
+    fn call(sysno: u64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = OUT;
+        evt.head.cause = USER_ECALL;
+        evt.head.ax[7] = sysno;
+        evt.out_head = Some(evt.head.clone());
+        evt
+    }
+
+    fn execve(path: &str) -> TraceEvent {
+        let mut evt = call(SYS_EXECVE);
+        let data = format!("{}\0", path).into_bytes();
+        evt.payloads.push(TracePayload { inout: IN, index: 0, data });
+        evt
+    }
+
+    fn flow(tid: u64, parent: Option<u64>, events: Vec<TraceEvent>) -> TraceFlow {
+        let mut flow = TraceFlow::new();
+        flow.tid = tid;
+        flow.parent = parent;
+        flow.events = events;
+        flow
+    }
+
+    fn calls(sysnos: &[u64]) -> Vec<TraceEvent> {
+        sysnos.iter().map(|&sysno| call(sysno)).collect()
+    }
+
+    #[test]
+    fn paths_in_the_process_tree() {
+        let flows = [
+            flow(0x100, None, vec![]),
+            flow(0x101, Some(0x100), vec![]),
+            flow(0x102, Some(0x100), vec![]),
+            flow(0x103, Some(0x102), vec![]),
+            flow(0x200, None, vec![]),
+        ];
+        assert_eq!(task_paths(&flows), ["0", "0.0", "0.1", "0.1.0", "1"]);
+    }
+
+    #[test]
+    fn similarity_of_syscall_names() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(similarity(&[], &[]), 1.0);
+        assert_eq!(similarity(&names(&["read", "write"]), &names(&["read", "close"])), 0.5);
+        assert_eq!(similarity(&names(&["read"]), &[]), 0.0);
+    }
+
+    #[test]
+    fn pairs_children_by_exec_image_before_clone_order() {
+        let left = [
+            flow(0x100, None, calls(&[SYS_CLONE, SYS_CLONE])),
+            flow(0x101, Some(0x100), vec![execve("/bin/ls"), call(SYS_WRITE)]),
+            flow(0x102, Some(0x100), vec![execve("/bin/cat"), call(SYS_READ)]),
+        ];
+        let right = [
+            flow(0x200, None, calls(&[SYS_CLONE, SYS_CLONE])),
+            flow(0x203, Some(0x200), vec![execve("/bin/cat"), call(SYS_READ)]),
+            flow(0x205, Some(0x200), vec![execve("/bin/ls"), call(SYS_WRITE)]),
+        ];
+        let matched = match_tasks(&left, &right);
+        let pairs = matched.pairs.iter().map(|&(l, r, _)| (l, r)).collect::<Vec<_>>();
+        assert_eq!(pairs, [(0, 0), (1, 2), (2, 1)]);
+        assert!(matched.left_only.is_empty() && matched.right_only.is_empty());
+        assert_eq!(matched.right_of(1), Some(2));
+        assert_eq!(matched.left_of(1), Some(2));
+    }
+
+    #[test]
+    fn pairs_siblings_greedily_by_score() {
+        let left = [
+            flow(0x100, None, calls(&[SYS_CLONE])),
+            flow(0x101, Some(0x100), calls(&[SYS_READ, SYS_WRITE, SYS_EXIT])),
+        ];
+        // The first child is a better pair by position, the second one by
+        // its syscalls.
+        let right = [
+            flow(0x200, None, calls(&[SYS_CLONE, SYS_CLONE])),
+            flow(0x201, Some(0x200), calls(&[SYS_GETPID])),
+            flow(0x202, Some(0x200), calls(&[SYS_READ, SYS_WRITE, SYS_EXIT])),
+        ];
+        let matched = match_tasks(&left, &right);
+        let pairs = matched.pairs.iter().map(|&(l, r, _)| (l, r)).collect::<Vec<_>>();
+        assert_eq!(pairs, [(0, 0), (1, 2)]);
+        assert_eq!(matched.pairs[1].2, 1.0);
+        assert_eq!(matched.right_only, [1]);
+        assert!(matched.left_only.is_empty());
+    }
+
+    #[test]
+    fn keeps_apart_tasks_of_other_images() {
+        let left = [flow(0x100, None, vec![execve("/bin/ls"), call(SYS_READ)])];
+        let right = [flow(0x200, None, vec![execve("/bin/sh"), call(SYS_WRITE)])];
+        let matched = match_tasks(&left, &right);
+        assert!(matched.pairs.is_empty());
+        assert_eq!((matched.left_only, matched.right_only), (vec![0], vec![0]));
+    }
+}
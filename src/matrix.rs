@@ -0,0 +1,282 @@
+//! Syscall coverage of a corpus of apps.
+//!
+//! Each app is a directory with the traces of Linux and lkmodel, named
+//! `linux.data` and `lk.data`. The traces are diffed as by `lkdiff diff`,
+//! and each syscall an app uses is marked as matching, diverging, or only
+//! used by tasks without a pair; so is each trap cause if traps are
+//! diffed. The matrix of apps by syscalls can be exported as CSV or
+//! Markdown.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use crate::decode::decode_event;
+use crate::divergence::{Divergence, Site};
+use crate::event::{SigStage, TraceEvent};
+use crate::flow::{TraceFlow, TraceFlows};
+use crate::report::DiffResult;
+use crate::trap::cause_name;
+use crate::waiver::Waiver;
+
+pub const LINUX_TRACE: &str = "linux.data";
+pub const LKMODEL_TRACE: &str = "lk.data";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    /// Only in tasks without a pair, so not compared
+    Used,
+    Match,
+    Diverge,
+}
+
+impl Coverage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Coverage::Used => "used",
+            Coverage::Match => "ok",
+            Coverage::Diverge => "diff",
+        }
+    }
+}
+
+pub struct AppResult {
+    pub app: String,
+    /// Divergences not waived
+    pub divergences: Vec<Divergence>,
+    /// Syscalls used by the app
+    pub syscalls: BTreeMap<String, Coverage>,
+}
+
+impl AppResult {
+    pub fn passed(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Diff the traces of an app; waived divergences are matching.
+    pub fn diff(app: &str, linux: &[TraceFlow], lkmodel: &[TraceFlow], waivers: &[Waiver], traps: bool) -> Self {
+        let mut result = DiffResult::new(linux, lkmodel, traps);
+        result.apply_waivers(waivers);
+        let (matched, divergences) = (result.matched, result.divergences);
+
+        let mut syscalls = BTreeMap::new();
+        let sides = [(linux, &matched.left_only), (lkmodel, &matched.right_only)];
+        for (flows, only) in sides {
+            for (i, flow) in flows.iter().enumerate() {
+                let coverage = if only.contains(&i) { Coverage::Used } else { Coverage::Match };
+                for name in syscall_names(flow, traps) {
+                    let cell = syscalls.entry(name).or_insert(coverage);
+                    if coverage == Coverage::Match {
+                        *cell = coverage;
+                    }
+                }
+            }
+        }
+        for div in &divergences {
+            for (flows, site) in [(linux, &div.left), (lkmodel, &div.right)] {
+                if let Some(name) = site_syscall(flows, site, traps) {
+                    syscalls.insert(name, Coverage::Diverge);
+                }
+            }
+        }
+        Self { app: app.to_string(), divergences, syscalls }
+    }
+}
+
+/// Syscall of an event, or its trap cause if `traps`.
+fn event_name(evt: &TraceEvent, traps: bool) -> Option<String> {
+    if evt.is_trap() {
+        traps.then(|| cause_name(evt.head.cause))
+    } else {
+        Some(decode_event(evt).name)
+    }
+}
+
+/// Syscalls of the flow, and trap causes if `traps`, leaving out signal
+/// stages.
+fn syscall_names(flow: &TraceFlow, traps: bool) -> BTreeSet<String> {
+    flow.events.iter()
+        .filter(|evt| evt.signal == SigStage::Empty)
+        .filter_map(|evt| event_name(evt, traps))
+        .collect()
+}
+
+fn site_syscall(flows: &[TraceFlow], site: &Option<Site>, traps: bool) -> Option<String> {
+    let site = site.as_ref()?;
+    let evt = &flows[site.task].events[site.index?];
+    if evt.signal != SigStage::Empty {
+        return None;
+    }
+    event_name(evt, traps)
+}
+
+pub struct Matrix {
+    /// In order of name
+    pub apps: Vec<AppResult>,
+}
+
+impl Matrix {
+    /// Diff each app of the directory which has both traces.
+    pub fn load(dir: &str, level: usize, partial: bool, waivers: &[Waiver], traps: bool) -> Result<Self> {
+        let mut dirs = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>>>()?;
+        dirs.sort();
+        let mut apps = vec![];
+        for path in dirs {
+            let (linux, lkmodel) = (path.join(LINUX_TRACE), path.join(LKMODEL_TRACE));
+            if !linux.is_file() || !lkmodel.is_file() {
+                continue;
+            }
+            let app = path.file_name().unwrap().to_string_lossy();
+            let linux = TraceFlows::load(&path_str(&linux), level, partial)?;
+            let lkmodel = TraceFlows::load(&path_str(&lkmodel), level, partial)?;
+            apps.push(AppResult::diff(&app, &linux.flows, &lkmodel.flows, waivers, traps));
+        }
+        Ok(Self { apps })
+    }
+
+    /// Syscalls used by any app, in order of name.
+    pub fn syscalls(&self) -> Vec<&str> {
+        let names = self.apps.iter()
+            .flat_map(|app| app.syscalls.keys())
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>();
+        names.into_iter().collect()
+    }
+
+    /// Rows of app, result, divergences and a cell per syscall.
+    fn rows(&self) -> Vec<Vec<String>> {
+        let syscalls = self.syscalls();
+        let mut header = vec!["app".to_string(), "result".to_string(), "divergences".to_string()];
+        header.extend(syscalls.iter().map(|name| name.to_string()));
+        let mut rows = vec![header];
+        for app in &self.apps {
+            let result = if app.passed() { "pass" } else { "fail" };
+            let mut row = vec![app.app.clone(), result.to_string(), app.divergences.len().to_string()];
+            row.extend(syscalls.iter().map(|&name| {
+                app.syscalls.get(name).map_or("", |cell| cell.name()).to_string()
+            }));
+            rows.push(row);
+        }
+        rows
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        for row in self.rows() {
+            let fields = row.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+            writeln!(out, "{}", fields.join(",")).unwrap();
+        }
+        out
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        for (i, row) in self.rows().iter().enumerate() {
+            writeln!(out, "| {} |", row.join(" | ")).unwrap();
+            if i == 0 {
+                writeln!(out, "|{}", "---|".repeat(row.len())).unwrap();
+            }
+        }
+        let passed = self.apps.iter().filter(|app| app.passed()).count();
+        writeln!(out).unwrap();
+        writeln!(out, "Passed: {}/{}", passed, self.apps.len()).unwrap();
+        out
+    }
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Quote a field with a comma, quote or newline.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::USER_ECALL;
+    use crate::sysno::*;
+    use crate::waiver::parse_waivers;
+    use crate::OUT;
+
+    fn call(sysno: u64, args: &[u64], result: i64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = OUT;
+        evt.head.cause = USER_ECALL;
+        evt.head.ax[7] = sysno;
+        evt.head.ax[..args.len()].copy_from_slice(args);
+        evt.out_head = Some(evt.head.clone());
+        evt.result = result;
+        evt
+    }
+
+    fn flow(tid: u64, parent: Option<u64>, events: Vec<TraceEvent>) -> TraceFlow {
+        let mut flow = TraceFlow::new();
+        flow.tid = tid;
+        flow.parent = parent;
+        flow.events = events;
+        flow
+    }
+
+    /// Linux writes to stdout, lkmodel to stderr, and only Linux has a
+    /// child which exits.
+    fn app(name: &str, waivers: &[Waiver]) -> AppResult {
+        let root = |tid, fd| flow(tid, None, vec![
+            call(SYS_GETPID, &[], 0x100),
+            call(SYS_WRITE, &[fd, 0x6000, 3], 3),
+            call(SYS_CLONE, &[0x11], 0x101),
+        ]);
+        let linux = [root(0x100, 1), flow(0x101, Some(0x100), vec![call(SYS_EXIT, &[0], 0)])];
+        let lkmodel = [root(0x200, 2)];
+        AppResult::diff(name, &linux, &lkmodel, waivers, false)
+    }
+
+    #[test]
+    fn marks_syscalls_by_coverage() {
+        let result = app("hello", &[]);
+        let cells = result.syscalls.iter().map(|(name, cell)| (name.as_str(), *cell)).collect::<Vec<_>>();
+        assert_eq!(cells, [
+            ("clone", Coverage::Match),
+            ("exit", Coverage::Used),
+            ("getpid", Coverage::Match),
+            ("write", Coverage::Diverge),
+        ]);
+        assert!(!result.passed());
+        assert_eq!(result.divergences.len(), 2);
+    }
+
+    #[test]
+    fn takes_waived_divergences_as_matching() {
+        let waivers = parse_waivers("[stderr]\nsyscall = write\n[child]\nclass = task_structure\n").unwrap();
+        let result = app("hello", &waivers);
+        assert!(result.passed());
+        assert_eq!(result.syscalls["write"], Coverage::Match);
+    }
+
+    #[test]
+    fn exports_csv_and_markdown() {
+        let matrix = Matrix { apps: vec![app("a,b", &[]), app("c", &parse_waivers("[all]\n").unwrap())] };
+        assert_eq!(matrix.to_csv(), "\
+app,result,divergences,clone,exit,getpid,write
+\"a,b\",fail,2,ok,used,ok,diff
+c,pass,0,ok,used,ok,ok
+");
+        assert_eq!(matrix.to_markdown(), "\
+| app | result | divergences | clone | exit | getpid | write |
+|---|---|---|---|---|---|---|
+| a,b | fail | 2 | ok | used | ok | diff |
+| c | pass | 0 | ok | used | ok | ok |
+
+Passed: 1/2
+");
+    }
+}
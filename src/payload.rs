@@ -33,14 +33,11 @@ fn parse_payload(reader: &mut BufReader<File>, inout: u64) -> Result<TracePayloa
     let mut buf = [0u8; PH_SIZE];
     reader.read_exact(&mut buf)?;
     let head = unsafe { mem::transmute::<[u8; PH_SIZE], PayloadHead>(buf) };
-    let mut data = Vec::with_capacity(head.size as usize);
-    unsafe {
-        data.set_len(head.size as usize);
-    }
+    let mut data = vec![0u8; head.size as usize];
     reader.read_exact(&mut data)?;
 
     Ok(TracePayload {
-        inout: inout,
+        inout,
         index: head.index as usize,
         data,
     })
@@ -0,0 +1,170 @@
+//! poll/select/epoll const and payload decoding.
+
+/// poll events
+pub const POLLIN: u64 = 0x001;
+pub const POLLPRI: u64 = 0x002;
+pub const POLLOUT: u64 = 0x004;
+pub const POLLERR: u64 = 0x008;
+pub const POLLHUP: u64 = 0x010;
+pub const POLLNVAL: u64 = 0x020;
+pub const POLLRDNORM: u64 = 0x040;
+pub const POLLRDBAND: u64 = 0x080;
+pub const POLLWRNORM: u64 = 0x100;
+pub const POLLWRBAND: u64 = 0x200;
+pub const POLLRDHUP: u64 = 0x2000;
+
+/// epoll events, beyond the ones shared with poll
+pub const EPOLLEXCLUSIVE: u64 = 1 << 28;
+pub const EPOLLWAKEUP: u64 = 1 << 29;
+pub const EPOLLONESHOT: u64 = 1 << 30;
+pub const EPOLLET: u64 = 1 << 31;
+
+/// epoll_ctl op
+pub const EPOLL_CTL_ADD: u64 = 1;
+pub const EPOLL_CTL_DEL: u64 = 2;
+pub const EPOLL_CTL_MOD: u64 = 3;
+
+pub const EPOLL_CLOEXEC: u64 = 0o2000000;
+
+/// struct pollfd { int fd; short events; short revents; }
+const POLLFD_SIZE: usize = 8;
+
+/// struct epoll_event { u32 events; u64 data; }, not packed on riscv64
+const EPOLL_EVENT_SIZE: usize = 16;
+
+fn poll_bits(events: u64, epoll: bool) -> String {
+    let mut names: Vec<String> = vec![];
+    let table = [
+        (POLLIN, "IN"),
+        (POLLPRI, "PRI"),
+        (POLLOUT, "OUT"),
+        (POLLERR, "ERR"),
+        (POLLHUP, "HUP"),
+        (POLLNVAL, "NVAL"),
+        (POLLRDNORM, "RDNORM"),
+        (POLLRDBAND, "RDBAND"),
+        (POLLWRNORM, "WRNORM"),
+        (POLLWRBAND, "WRBAND"),
+        (POLLRDHUP, "RDHUP"),
+    ];
+    let prefix = if epoll { "EPOLL" } else { "POLL" };
+    let mut rest = events;
+    for (bit, name) in table {
+        if events & bit != 0 {
+            names.push(format!("{}{}", prefix, name));
+            rest &= !bit;
+        }
+    }
+    if epoll {
+        for (bit, name) in [
+            (EPOLLEXCLUSIVE, "EPOLLEXCLUSIVE"),
+            (EPOLLWAKEUP, "EPOLLWAKEUP"),
+            (EPOLLONESHOT, "EPOLLONESHOT"),
+            (EPOLLET, "EPOLLET"),
+        ] {
+            if events & bit != 0 {
+                names.push(name.to_string());
+                rest &= !bit;
+            }
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    if !names.is_empty() {
+        names.join("|")
+    } else {
+        String::from("0")
+    }
+}
+
+pub fn poll_event_name(events: u64) -> String {
+    poll_bits(events, false)
+}
+
+pub fn epoll_event_name(events: u64) -> String {
+    poll_bits(events, true)
+}
+
+pub fn epoll_ctl_op_name(op: u64) -> String {
+    match op {
+        EPOLL_CTL_ADD => "EPOLL_CTL_ADD".to_string(),
+        EPOLL_CTL_DEL => "EPOLL_CTL_DEL".to_string(),
+        EPOLL_CTL_MOD => "EPOLL_CTL_MOD".to_string(),
+        _ => format!("{:#x}", op),
+    }
+}
+
+pub fn epoll_create_flag_name(flags: u64) -> String {
+    if flags == EPOLL_CLOEXEC {
+        String::from("EPOLL_CLOEXEC")
+    } else {
+        format!("{:#x}", flags)
+    }
+}
+
+/// Decoded struct pollfd.
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+pub fn parse_pollfds(data: &[u8]) -> Vec<PollFd> {
+    data.chunks_exact(POLLFD_SIZE).map(|chunk| {
+        PollFd {
+            fd: i32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+            events: u16::from_ne_bytes([chunk[4], chunk[5]]),
+            revents: u16::from_ne_bytes([chunk[6], chunk[7]]),
+        }
+    }).collect()
+}
+
+/// Requested events of a pollfd array.
+pub fn pollfds_name(fds: &[PollFd]) -> String {
+    let fds = fds.iter()
+        .map(|p| format!("{{fd={}, events={}}}", p.fd, poll_event_name(p.events as u64)))
+        .collect::<Vec<_>>();
+    format!("[{}]", fds.join(", "))
+}
+
+/// Returned events of a pollfd array; only entries with revents.
+pub fn pollfds_revents_name(fds: &[PollFd]) -> String {
+    let fds = fds.iter()
+        .filter(|p| p.revents != 0)
+        .map(|p| format!("{{fd={}, revents={}}}", p.fd, poll_event_name(p.revents as u64)))
+        .collect::<Vec<_>>();
+    format!("[{}]", fds.join(", "))
+}
+
+pub fn epoll_events_name(data: &[u8], count: usize) -> String {
+    let size = if data.len().is_multiple_of(EPOLL_EVENT_SIZE) { EPOLL_EVENT_SIZE } else { 12 };
+    let events = data.chunks_exact(size)
+        .take(count)
+        .map(epoll_event_struct_name)
+        .collect::<Vec<_>>();
+    format!("[{}]", events.join(", "))
+}
+
+pub fn epoll_event_struct_name(data: &[u8]) -> String {
+    if data.len() < 12 {
+        return format!("{:?}", data);
+    }
+    let events = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
+    let off = if data.len() >= EPOLL_EVENT_SIZE { 8 } else { 4 };
+    let mut buf = [0u8; 8];
+    buf.clone_from_slice(&data[off..off + 8]);
+    let udata = u64::from_ne_bytes(buf);
+    format!("{{events={}, data={:#x}}}", epoll_event_name(events as u64), udata)
+}
+
+/// fd_set bitmap as a list of fds below nfds.
+pub fn fd_set_name(data: &[u8], nfds: usize) -> String {
+    let mut fds = vec![];
+    for fd in 0..nfds.min(data.len() * 8) {
+        if data[fd / 8] & (1 << (fd % 8)) != 0 {
+            fds.push(fd.to_string());
+        }
+    }
+    format!("[{}]", fds.join(" "))
+}
@@ -0,0 +1,314 @@
+//! Normalization profile learned from repeated runs of Linux.
+//!
+//! Level 2 hides the fields known to differ between runs. A profile lists
+//! more of them, found by aligning several Linux traces of the same app:
+//! any arg, result or struct field which varies between runs is
+//! nondeterministic, and is hidden once the profile is set, as level 2 does.
+//!
+//! A profile has a field per line, `<syscall> <field>`, where the field is
+//! `result`, `arg<n>`, or `arg<n>.<name>` for a field of a struct, by name
+//! or by position. `#` starts a comment.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::RwLock;
+use once_cell::sync::Lazy;
+use crate::align::{align, EditOp, EventEq, ResultCmp};
+use crate::decode::{decode_event, ArgValue, DecodedCall, SysResult};
+use crate::event::{SigStage, TraceEvent};
+use crate::flow::TraceFlow;
+use crate::matching::match_tasks;
+
+/// Shown for a hidden value without a name.
+const VARIES: &str = "%varies%";
+
+static PROFILE: Lazy<RwLock<Profile>> = Lazy::new(|| RwLock::new(Profile::default()));
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// (syscall, field)
+    fields: BTreeSet<(String, String)>,
+}
+
+impl Profile {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn contains(&self, syscall: &str, field: &str) -> bool {
+        self.fields.contains(&(syscall.to_string(), field.to_string()))
+    }
+
+    pub fn insert(&mut self, syscall: &str, field: &str) {
+        self.fields.insert((syscall.to_string(), field.to_string()));
+    }
+
+    pub fn load(fname: &str) -> Result<Self> {
+        Self::parse(&fs::read_to_string(fname)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut profile = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [syscall, field] => profile.insert(syscall, field),
+                _ => {
+                    let msg = format!("profile line {}: expected <syscall> <field>", i + 1);
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                },
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Hide the fields of the call in the profile.
+    pub fn normalize(&self, call: &mut DecodedCall) {
+        if self.contains(&call.name, "result") {
+            call.result = match &call.result {
+                SysResult::Ok { raw, .. } => SysResult::Ok { raw: *raw, text: VARIES.to_string() },
+                SysResult::Err { errno, .. } => SysResult::Err { errno: *errno, text: VARIES.to_string() },
+            };
+        }
+        for (i, arg) in call.args.iter_mut().enumerate() {
+            let field = format!("arg{}", i);
+            if self.contains(&call.name, &field) {
+                arg.value = ArgValue::Symbol(VARIES.to_string());
+                continue;
+            }
+            let text = arg.value.to_string();
+            let mut elems = match split_struct(&text) {
+                Some(elems) => elems,
+                None => continue,
+            };
+            let mut hidden = false;
+            for (j, elem) in elems.iter_mut().enumerate() {
+                let key = elem_key(elem, j);
+                if self.contains(&call.name, &format!("{}.{}", field, key)) {
+                    *elem = match elem_name(elem) {
+                        Some(name) => name.to_string(),
+                        None => VARIES.to_string(),
+                    };
+                    hidden = true;
+                }
+            }
+            if hidden {
+                arg.value = ArgValue::Struct(format!("{{{}}}", elems.join(", ")));
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (syscall, field) in &self.fields {
+            writeln!(f, "{} {}", syscall, field)?;
+        }
+        Ok(())
+    }
+}
+
+/// Set the profile applied by [`decode_event`].
+pub fn set_profile(profile: Profile) {
+    *PROFILE.write().unwrap() = profile;
+}
+
+pub(crate) fn normalize(call: &mut DecodedCall) {
+    let profile = PROFILE.read().unwrap();
+    if !profile.is_empty() {
+        profile.normalize(call);
+    }
+}
+
+/// Elements of a struct rendered as `{a, b, ...}`, split at the top level.
+fn split_struct(text: &str) -> Option<Vec<String>> {
+    let inner = text.strip_prefix('{')?.strip_suffix('}')?;
+    let mut elems = vec![];
+    let mut elem = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in inner.chars() {
+        if quoted {
+            quoted = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else {
+            match c {
+                '"' => quoted = true,
+                '{' | '[' | '(' => depth += 1,
+                '}' | ']' | ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    elems.push(elem.trim().to_string());
+                    elem.clear();
+                    continue;
+                },
+                _ => {},
+            }
+        }
+        elem.push(c);
+    }
+    if !elem.trim().is_empty() || !elems.is_empty() {
+        elems.push(elem.trim().to_string());
+    }
+    Some(elems)
+}
+
+/// Name of a `name=value` or `name: value` element.
+fn elem_name(elem: &str) -> Option<&str> {
+    let end = elem.find(['=', ':'])?;
+    let name = &elem[..end];
+    let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_name.then_some(name)
+}
+
+/// The name of the element, or its position.
+fn elem_key(elem: &str, index: usize) -> String {
+    match elem_name(elem) {
+        Some(name) => name.to_string(),
+        None => index.to_string(),
+    }
+}
+
+/// How often a field has varied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Variation {
+    pub varied: usize,
+    pub compared: usize,
+}
+
+/// Fields of calls aligned between runs, with how often they have varied.
+#[derive(Default)]
+pub struct Learner {
+    /// (syscall, field) to its variation
+    pub fields: BTreeMap<(String, String), Variation>,
+}
+
+impl Learner {
+    /// Compare a run with the first one, each with flows ordered by start.
+    pub fn compare(&mut self, base: &[TraceFlow], run: &[TraceFlow]) {
+        let matched = match_tasks(base, run);
+        // Align by syscall only, since the args may vary; fields are
+        // compared between calls of the same syscall.
+        let eq = EventEq { args: false, result: ResultCmp::Ignore, equiv: false };
+        for &(l, r, _) in &matched.pairs {
+            let (left, right) = (&base[l].events, &run[r].events);
+            for op in align(left, right, &eq) {
+                if let EditOp::Equal(i, j) = op {
+                    self.compare_event(&left[i], &right[j]);
+                }
+            }
+        }
+    }
+
+    fn compare_event(&mut self, left: &TraceEvent, right: &TraceEvent) {
+        if left.is_trap() || left.signal != SigStage::Empty || right.signal != SigStage::Empty {
+            return;
+        }
+        let (lcall, rcall) = (decode_event(left), decode_event(right));
+        if lcall.result.is_err() == rcall.result.is_err() {
+            self.record(&lcall.name, "result".to_string(), lcall.result.text() != rcall.result.text());
+        }
+        for (i, (larg, rarg)) in lcall.args.iter().zip(&rcall.args).enumerate() {
+            let (ltext, rtext) = (larg.value.to_string(), rarg.value.to_string());
+            let field = format!("arg{}", i);
+            match (split_struct(&ltext), split_struct(&rtext)) {
+                (Some(lelems), Some(relems)) if lelems.len() == relems.len() => {
+                    for (j, (lelem, relem)) in lelems.iter().zip(&relems).enumerate() {
+                        let key = format!("{}.{}", field, elem_key(lelem, j));
+                        self.record(&lcall.name, key, lelem != relem);
+                    }
+                },
+                _ => self.record(&lcall.name, field, ltext != rtext),
+            }
+        }
+    }
+
+    fn record(&mut self, syscall: &str, field: String, varied: bool) {
+        let variation = self.fields.entry((syscall.to_string(), field)).or_default();
+        variation.compared += 1;
+        if varied {
+            variation.varied += 1;
+        }
+    }
+
+    /// The fields which have varied.
+    pub fn profile(&self) -> Profile {
+        let mut profile = Profile::default();
+        for ((syscall, field), variation) in &self.fields {
+            if variation.varied != 0 {
+                profile.insert(syscall, field);
+            }
+        }
+        profile
+    }
+
+    /// The profile, with how often each field has varied.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# Fields which vary between runs of Linux").unwrap();
+        for ((syscall, field), variation) in &self.fields {
+            if variation.varied != 0 {
+                let line = format!("{} {}", syscall, field);
+                writeln!(out, "{:<40}# {}/{}", line, variation.varied, variation.compared).unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::DecodedArg;
+
+    #[test]
+    fn parse() {
+        let text = "# Fields which vary between runs of Linux\n\
+            fstat arg1.dev      # 2/2\n\
+            \n\
+            mmap   result\n";
+        let profile = Profile::parse(text).unwrap();
+        assert!(profile.contains("fstat", "arg1.dev"));
+        assert!(profile.contains("mmap", "result"));
+        assert!(!profile.contains("mmap", "arg0"));
+        assert_eq!(profile.to_string(), "fstat arg1.dev\nmmap result\n");
+        assert_eq!(Profile::parse(&profile.to_string()).unwrap(), profile);
+
+        let err = Profile::parse("mmap\n").unwrap_err();
+        assert_eq!(err.to_string(), "profile line 1: expected <syscall> <field>");
+        assert!(Profile::parse("# only\nmmap result arg0\n").is_err());
+    }
+
+    #[test]
+    fn split() {
+        let elems = |text: &str| split_struct(text).unwrap();
+        assert_eq!(split_struct("0x10"), None);
+        assert_eq!(split_struct("[1, 2]"), None);
+        assert_eq!(elems("{}"), Vec::<String>::new());
+        assert_eq!(elems("{dev=0x1, ino=2}"), ["dev=0x1", "ino=2"]);
+        assert_eq!(elems("{tv_sec=1, {a, b}, [c, d], f(e, g)}"), ["tv_sec=1", "{a, b}", "[c, d]", "f(e, g)"]);
+        assert_eq!(elems(r#"{"a, b", "c\", d", e}"#), [r#""a, b""#, r#""c\", d""#, "e"]);
+        assert_eq!(elems("{, x}"), ["", "x"]);
+    }
+
+    #[test]
+    fn normalize() {
+        let profile = Profile::parse("uname arg0.2\nuname arg0.release\nuname result\n").unwrap();
+        let mut call = DecodedCall {
+            name: "uname".to_string(),
+            args: vec![DecodedArg {
+                raw: 0x1000,
+                value: ArgValue::Struct("{sysname: \"Linux\", nodename: \"a\", \"x\", release: \"6.1\"}".to_string()),
+            }],
+            result: SysResult::new(0, "0x0".to_string()),
+        };
+        profile.normalize(&mut call);
+        assert_eq!(call.args[0].value.to_string(), "{sysname: \"Linux\", nodename: \"a\", %varies%, release}");
+        assert_eq!(call.result, SysResult::Ok { raw: 0, text: VARIES.to_string() });
+    }
+}
@@ -0,0 +1,262 @@
+//! Result of diffing Linux and lkmodel, for a pipeline to gate on.
+//!
+//! The result is written as JSON: the matched tasks, and each divergence
+//! with its class and sites in both traces, apart from those waived. The
+//! status is also the exit code of `lkdiff diff`.
+
+use std::fmt::Write;
+use crate::divergence::{diff_tasks, Divergence, Site};
+use crate::flow::TraceFlow;
+use crate::matching::{match_tasks, TaskMatch};
+use crate::waiver::Waiver;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffStatus {
+    Identical,
+    /// All divergences are waived
+    Waived,
+    Different,
+}
+
+impl DiffStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiffStatus::Identical => "identical",
+            DiffStatus::Waived => "waived",
+            DiffStatus::Different => "different",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DiffStatus::Identical => 0,
+            DiffStatus::Waived => 1,
+            DiffStatus::Different => 2,
+        }
+    }
+}
+
+/// A divergence suppressed by a waiver.
+pub struct Waived {
+    pub divergence: Divergence,
+    pub waiver: Waiver,
+}
+
+pub struct DiffResult {
+    pub matched: TaskMatch,
+    /// New divergences, not waived
+    pub divergences: Vec<Divergence>,
+    pub waived: Vec<Waived>,
+    /// Waivers which have matched nothing
+    pub unused_waivers: Vec<Waiver>,
+}
+
+impl DiffResult {
+    /// Diff two traces, each with flows ordered by start, comparing traps
+    /// if `traps`.
+    pub fn new(linux: &[TraceFlow], lkmodel: &[TraceFlow], traps: bool) -> Self {
+        let matched = match_tasks(linux, lkmodel);
+        let divergences = diff_tasks(linux, lkmodel, &matched, traps);
+        Self { matched, divergences, waived: vec![], unused_waivers: vec![] }
+    }
+
+    /// Move the divergences matched by a waiver to `waived`, the first
+    /// matching waiver taken.
+    pub fn apply_waivers(&mut self, waivers: &[Waiver]) {
+        let mut used = vec![false; waivers.len()];
+        let mut divergences = vec![];
+        for div in self.divergences.drain(..) {
+            match waivers.iter().position(|waiver| waiver.matches(&div)) {
+                Some(i) => {
+                    used[i] = true;
+                    self.waived.push(Waived { divergence: div, waiver: waivers[i].clone() });
+                },
+                None => divergences.push(div),
+            }
+        }
+        self.divergences = divergences;
+        self.unused_waivers = waivers.iter().zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(waiver, _)| waiver.clone())
+            .collect();
+    }
+
+    pub fn status(&self) -> DiffStatus {
+        if !self.divergences.is_empty() {
+            DiffStatus::Different
+        } else if !self.waived.is_empty() {
+            DiffStatus::Waived
+        } else {
+            DiffStatus::Identical
+        }
+    }
+
+    /// The result as a JSON object, with the paths of the traces.
+    pub fn to_json(
+        &self, linux: &[TraceFlow], lkmodel: &[TraceFlow], linux_path: &str, lkmodel_path: &str
+    ) -> String {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"linux\": {},", json_str(linux_path)).unwrap();
+        writeln!(out, "  \"lkmodel\": {},", json_str(lkmodel_path)).unwrap();
+        writeln!(out, "  \"status\": {},", json_str(self.status().name())).unwrap();
+        let pairs = self.matched.pairs.iter().map(|&(l, r, sim)| {
+            format!(
+                "{{\"linux\": {}, \"lkmodel\": {}, \"similarity\": {:.2}}}",
+                json_str(&linux[l].name()),
+                json_str(&lkmodel[r].name()),
+                sim
+            )
+        }).collect::<Vec<_>>();
+        writeln!(out, "  \"matched\": {},", json_list(&pairs, 2)).unwrap();
+        let names = |only: &[usize], flows: &[TraceFlow]| {
+            only.iter().map(|&i| json_str(&flows[i].name())).collect::<Vec<_>>()
+        };
+        writeln!(out, "  \"linux_only\": [{}],", names(&self.matched.left_only, linux).join(", ")).unwrap();
+        writeln!(out, "  \"lkmodel_only\": [{}],", names(&self.matched.right_only, lkmodel).join(", ")).unwrap();
+        let divs = self.divergences.iter().map(|div| divergence_json(div, None)).collect::<Vec<_>>();
+        writeln!(out, "  \"divergences\": {},", json_list(&divs, 2)).unwrap();
+        let waived = self.waived.iter().map(|waived| {
+            divergence_json(&waived.divergence, Some(&waived.waiver))
+        }).collect::<Vec<_>>();
+        writeln!(out, "  \"waived\": {},", json_list(&waived, 2)).unwrap();
+        let unused = self.unused_waivers.iter().map(|waiver| {
+            format!("{{\"id\": {}, \"line\": {}}}", json_str(&waiver.id), waiver.line)
+        }).collect::<Vec<_>>();
+        writeln!(out, "  \"unused_waivers\": {}", json_list(&unused, 2)).unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+fn divergence_json(div: &Divergence, waiver: Option<&Waiver>) -> String {
+    let opt = |val: &Option<String>| val.as_deref().map_or("null".to_string(), json_str);
+    let non_empty = |val: &str| if val.is_empty() { "null".to_string() } else { json_str(val) };
+    let mut fields = vec![];
+    if let Some(waiver) = waiver {
+        fields.push(format!("\"waiver\": {}", json_str(&waiver.id)));
+        fields.push(format!("\"reason\": {}", non_empty(&waiver.reason)));
+        fields.push(format!("\"ticket\": {}", non_empty(&waiver.ticket)));
+    }
+    fields.extend([
+        format!("\"class\": {}", json_str(div.class.kind())),
+        format!("\"syscall\": {}", opt(&div.syscall)),
+        format!("\"field\": {}", opt(&div.field)),
+        format!("\"linux\": {}", site_json(&div.left)),
+        format!("\"lkmodel\": {}", site_json(&div.right)),
+        format!("\"linux_value\": {}", opt(&div.left_value)),
+        format!("\"lkmodel_value\": {}", opt(&div.right_value)),
+        format!("\"detail\": {}", json_str(&div.detail)),
+    ]);
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn site_json(site: &Option<Site>) -> String {
+    let site = match site {
+        Some(site) => site,
+        None => return "null".to_string(),
+    };
+    let opt = |val: Option<String>| val.unwrap_or_else(|| "null".to_string());
+    format!(
+        "{{\"task\": {}, \"path\": {}, \"index\": {}, \"offset\": {}}}",
+        json_str(&site.name),
+        json_str(&site.path),
+        opt(site.index.map(|index| index.to_string())),
+        opt(site.offset.map(|offset| offset.to_string()))
+    )
+}
+
+/// A list of JSON values, one per line.
+fn json_list(items: &[String], indent: usize) -> String {
+    if items.is_empty() {
+        return "[]".to_string();
+    }
+    let pad = " ".repeat(indent);
+    let items = items.iter()
+        .map(|item| format!("{}  {}", pad, item))
+        .collect::<Vec<_>>();
+    format!("[\n{}\n{}]", items.join(",\n"), pad)
+}
+
+pub fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{TraceEvent, USER_ECALL};
+    use crate::sysno::*;
+    use crate::waiver::parse_waivers;
+    use crate::OUT;
+
+    fn call(sysno: u64, args: &[u64], result: i64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = OUT;
+        evt.head.cause = USER_ECALL;
+        evt.head.ax[7] = sysno;
+        evt.head.ax[..args.len()].copy_from_slice(args);
+        evt.out_head = Some(evt.head.clone());
+        evt.result = result;
+        evt
+    }
+
+    /// A task writing to fd.
+    fn trace(tid: u64, fd: u64) -> Vec<TraceFlow> {
+        let mut flow = TraceFlow::new();
+        flow.tid = tid;
+        flow.events = vec![call(SYS_GETPID, &[], 0x100), call(SYS_WRITE, &[fd, 0x6000, 3], 3)];
+        vec![flow]
+    }
+
+    #[test]
+    fn exit_codes_by_status() {
+        let (linux, same, other) = (trace(0x100, 1), trace(0x200, 1), trace(0x200, 2));
+        let result = DiffResult::new(&linux, &same, false);
+        assert_eq!((result.status(), result.status().exit_code()), (DiffStatus::Identical, 0));
+
+        let mut result = DiffResult::new(&linux, &other, false);
+        assert_eq!((result.status(), result.status().exit_code()), (DiffStatus::Different, 2));
+        let waivers = parse_waivers("[read]\nsyscall = read\n[stderr]\nsyscall = write\nfield = arg0\n").unwrap();
+        result.apply_waivers(&waivers);
+        assert_eq!((result.status(), result.status().exit_code()), (DiffStatus::Waived, 1));
+        assert!(result.divergences.is_empty());
+        assert_eq!(result.waived[0].waiver.id, "stderr");
+        assert_eq!(result.unused_waivers.iter().map(|waiver| waiver.id.as_str()).collect::<Vec<_>>(), ["read"]);
+    }
+
+    #[test]
+    fn writes_json() {
+        let (linux, lkmodel) = (trace(0x100, 1), trace(0x200, 2));
+        let result = DiffResult::new(&linux, &lkmodel, false);
+        assert_eq!(result.to_json(&linux, &lkmodel, "linux.data", "lk\"model.data"), r#"{
+  "linux": "linux.data",
+  "lkmodel": "lk\"model.data",
+  "status": "different",
+  "matched": [
+    {"linux": "0x100", "lkmodel": "0x200", "similarity": 1.00}
+  ],
+  "linux_only": [],
+  "lkmodel_only": [],
+  "divergences": [
+    {"class": "argument", "syscall": "write", "field": "arg0", "linux": {"task": "0x100", "path": "0", "index": 1, "offset": 0}, "lkmodel": {"task": "0x200", "path": "0", "index": 1, "offset": 0}, "linux_value": "1", "lkmodel_value": "2", "detail": "write(1, 0x6000, 0x3) -> 0x3 vs write(2, 0x6000, 0x3) -> 0x3"}
+  ],
+  "waived": [],
+  "unused_waivers": []
+}
+"#);
+    }
+}
@@ -0,0 +1,180 @@
+//! clone flags, clone3 args, wait options and status
+
+use crate::signal::sig_name;
+
+pub const CSIGNAL: u64 = 0x000000ff;
+pub const CLONE_VM: u64 = 0x00000100;
+pub const CLONE_FS: u64 = 0x00000200;
+pub const CLONE_FILES: u64 = 0x00000400;
+pub const CLONE_SIGHAND: u64 = 0x00000800;
+pub const CLONE_PIDFD: u64 = 0x00001000;
+pub const CLONE_PTRACE: u64 = 0x00002000;
+pub const CLONE_VFORK: u64 = 0x00004000;
+pub const CLONE_PARENT: u64 = 0x00008000;
+pub const CLONE_THREAD: u64 = 0x00010000;
+pub const CLONE_SYSVSEM: u64 = 0x00040000;
+pub const CLONE_SETTLS: u64 = 0x00080000;
+pub const CLONE_PARENT_SETTID: u64 = 0x00100000;
+pub const CLONE_CHILD_CLEARTID: u64 = 0x00200000;
+pub const CLONE_CHILD_SETTID: u64 = 0x01000000;
+pub const CLONE_INTO_CGROUP: u64 = 0x200000000;
+
+/// Size of the first version of `struct clone_args`, without set_tid and
+/// cgroup.
+pub const CLONE_ARGS_SIZE_VER0: usize = 64;
+
+/// `struct clone_args` of clone3.
+#[derive(Clone, Debug, Default)]
+pub struct CloneArgs {
+    pub flags: u64,
+    pub pidfd: u64,
+    pub child_tid: u64,
+    pub parent_tid: u64,
+    pub exit_signal: u64,
+    pub stack: u64,
+    pub stack_size: u64,
+    pub tls: u64,
+    pub set_tid: u64,
+    pub set_tid_size: u64,
+}
+
+impl CloneArgs {
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < CLONE_ARGS_SIZE_VER0 {
+            return None;
+        }
+        let field = |i: usize| {
+            if data.len() < (i + 1) * 8 {
+                return 0;
+            }
+            let mut buf = [0u8; 8];
+            buf.clone_from_slice(&data[i * 8..(i + 1) * 8]);
+            u64::from_ne_bytes(buf)
+        };
+        Some(Self {
+            flags: field(0),
+            pidfd: field(1),
+            child_tid: field(2),
+            parent_tid: field(3),
+            exit_signal: field(4),
+            stack: field(5),
+            stack_size: field(6),
+            tls: field(7),
+            set_tid: field(8),
+            set_tid_size: field(9),
+        })
+    }
+}
+
+impl std::fmt::Display for CloneArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{flags={:#x}, exit_signal={}, stack={:#x}, stack_size={:#x}, tls={:#x}}}",
+            self.flags,
+            self.exit_signal,
+            self.stack,
+            self.stack_size,
+            self.tls
+        )
+    }
+}
+
+/// idtype of waitid
+pub const P_ALL: u64 = 0;
+pub const P_PID: u64 = 1;
+pub const P_PGID: u64 = 2;
+pub const P_PIDFD: u64 = 3;
+
+pub fn idtype_name(idtype: u64) -> String {
+    match idtype {
+        P_ALL => "P_ALL".to_string(),
+        P_PID => "P_PID".to_string(),
+        P_PGID => "P_PGID".to_string(),
+        P_PIDFD => "P_PIDFD".to_string(),
+        _ => format!("{:#x}", idtype),
+    }
+}
+
+/// Options of wait4 and waitid
+pub const WNOHANG: u64 = 0x00000001;
+pub const WUNTRACED: u64 = 0x00000002;
+pub const WSTOPPED: u64 = WUNTRACED;
+pub const WEXITED: u64 = 0x00000004;
+pub const WCONTINUED: u64 = 0x00000008;
+pub const WNOWAIT: u64 = 0x01000000;
+
+/// Offset of si_pid in the siginfo of a child, after signo, errno, code
+/// and padding.
+const SI_PID_OFFSET: usize = 16;
+
+/// Offset of si_code in siginfo.
+const SI_CODE_OFFSET: usize = 8;
+/// Offset of si_status in the siginfo of a child, after si_pid and si_uid.
+const SI_STATUS_OFFSET: usize = 24;
+
+/// si_code of SIGCHLD
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_TRAPPED: i32 = 4;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(i32::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+/// si_pid of the siginfo filled by waitid.
+pub fn siginfo_pid(data: &[u8]) -> Option<i32> {
+    read_i32(data, SI_PID_OFFSET)
+}
+
+/// si_code and si_status of the siginfo filled by waitid.
+pub fn siginfo_status(data: &[u8]) -> Option<(i32, i32)> {
+    Some((read_i32(data, SI_CODE_OFFSET)?, read_i32(data, SI_STATUS_OFFSET)?))
+}
+
+pub fn cld_code_name(code: i32) -> String {
+    match code {
+        CLD_EXITED => "CLD_EXITED".to_string(),
+        CLD_KILLED => "CLD_KILLED".to_string(),
+        CLD_DUMPED => "CLD_DUMPED".to_string(),
+        CLD_TRAPPED => "CLD_TRAPPED".to_string(),
+        CLD_STOPPED => "CLD_STOPPED".to_string(),
+        CLD_CONTINUED => "CLD_CONTINUED".to_string(),
+        _ => code.to_string(),
+    }
+}
+
+/// The status wait4 stores for a child reported by waitid with si_code
+/// and si_status.
+pub fn wait_status(code: i32, status: i32) -> Option<i32> {
+    match code {
+        CLD_EXITED => Some((status & 0xff) << 8),
+        CLD_KILLED => Some(status & 0x7f),
+        CLD_DUMPED => Some((status & 0x7f) | 0x80),
+        CLD_TRAPPED | CLD_STOPPED => Some(((status & 0xff) << 8) | 0x7f),
+        CLD_CONTINUED => Some(0xffff),
+        _ => None,
+    }
+}
+
+/// Status of wait4 as tested by the W* macros, e.g.
+/// `[{WIFEXITED(s) && WEXITSTATUS(s) == 0}]`.
+pub fn wait_status_name(status: i32) -> String {
+    let (sig, code) = ((status & 0x7f) as u64, ((status >> 8) & 0xff) as u64);
+    let test = if status == 0xffff {
+        "WIFCONTINUED(s)".to_string()
+    } else if sig == 0 {
+        format!("WIFEXITED(s) && WEXITSTATUS(s) == {}", code)
+    } else if sig == 0x7f {
+        format!("WIFSTOPPED(s) && WSTOPSIG(s) == {}", sig_name(code))
+    } else if status & 0x80 != 0 {
+        format!("WIFSIGNALED(s) && WTERMSIG(s) == {} && WCOREDUMP(s)", sig_name(sig))
+    } else {
+        format!("WIFSIGNALED(s) && WTERMSIG(s) == {}", sig_name(sig))
+    };
+    format!("[{{{}}}]", test)
+}
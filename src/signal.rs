@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use crate::sysno::*;
 
@@ -5,20 +6,35 @@ pub const SIG_BLOCK:    u64 = 0; // for blocking signals
 pub const SIG_UNBLOCK:  u64 = 1; // for unblocking signals
 pub const SIG_SETMASK:  u64 = 2; // for setting the signal mask
 
+pub const SIGKILL: u64 = 9;
+pub const SIGSTOP: u64 = 19;
+
 // Note: No restorer in sigaction for riscv64.
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct SigAction {
     pub handler: usize,
     pub flags: usize,
     pub mask: usize,
 }
 
-#[allow(dead_code)]
 pub const NSIG: usize = 64;
 
+/// special handlers
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
 /// signal action flags
-pub const SA_RESTORER: usize = 0x4000000;
+pub const SA_NOCLDSTOP: usize = 0x00000001;
+pub const SA_NOCLDWAIT: usize = 0x00000002;
+pub const SA_SIGINFO: usize = 0x00000004;
+pub const SA_RESTORER: usize = 0x04000000;
+pub const SA_ONSTACK: usize = 0x08000000;
 pub const SA_RESTART: usize = 0x10000000;
+pub const SA_NODEFER: usize = 0x40000000;
+pub const SA_RESETHAND: usize = 0x80000000;
+
+/// struct sigaction { handler; flags; mask; }
+pub const SIGACTION_SIZE: usize = 24;
 
 /*
  * if a blocked call to one of the following interfaces is
@@ -35,33 +51,152 @@ pub const RESTART_SYSCALLS: [u64;5] = [
     SYS_WAIT4,
 ];
 
+impl SigAction {
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < SIGACTION_SIZE {
+            return None;
+        }
+        let field = |i: usize| {
+            let mut buf = [0u8; 8];
+            buf.clone_from_slice(&data[i * 8..(i + 1) * 8]);
+            u64::from_ne_bytes(buf) as usize
+        };
+        Some(Self {
+            handler: field(0),
+            flags: field(1),
+            mask: field(2),
+        })
+    }
+}
+
 impl Display for SigAction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{{ handler: {:#x}, flags: {}, mask: {:#x} }}",
-            self.handler,
+            "{{ handler: {}, flags: {}, mask: {} }}",
+            handler_name(self.handler),
             sa_flag_name(self.flags),
-            self.mask
+            sigset_name(self.mask as u64)
         )
     }
 }
 
+/// Signal dispositions of a process, shared by threads with CLONE_SIGHAND.
+#[derive(Clone, Debug, Default)]
+pub struct SigHand {
+    actions: BTreeMap<u64, SigAction>,
+}
+
+impl SigHand {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, signum: u64) -> Option<&SigAction> {
+        self.actions.get(&signum)
+    }
+
+    pub fn set(&mut self, signum: u64, action: SigAction) {
+        if action.handler == SIG_DFL {
+            self.actions.remove(&signum);
+        } else {
+            self.actions.insert(signum, action);
+        }
+    }
+
+    /// Restore the default action on delivery, for SA_RESETHAND.
+    pub fn deliver(&mut self, signum: u64) {
+        if let Some(action) = self.actions.get(&signum) {
+            if action.flags & SA_RESETHAND != 0 {
+                self.actions.remove(&signum);
+            }
+        }
+    }
+
+    /// Whether the handler of the signal is at addr. Signals may share a
+    /// handler, so the signal is not told by addr.
+    pub fn is_handler(&self, signum: u64, addr: usize) -> bool {
+        if addr == SIG_DFL || addr == SIG_IGN {
+            return false;
+        }
+        self.actions.get(&signum).is_some_and(|action| action.handler == addr)
+    }
+
+    /// Dispositions after execve: handled signals are reset to default,
+    /// ignored ones stay ignored.
+    pub fn exec(&self) -> Self {
+        let actions = self.actions.iter()
+            .filter(|(_, action)| action.handler == SIG_IGN)
+            .map(|(signum, action)| (*signum, *action))
+            .collect();
+        Self { actions }
+    }
+}
+
+pub fn handler_name(handler: usize) -> String {
+    match handler {
+        SIG_DFL => "SIG_DFL".to_string(),
+        SIG_IGN => "SIG_IGN".to_string(),
+        _ => format!("{:#x}", handler),
+    }
+}
+
 pub fn sa_flag_name(sa_flags: usize) -> String {
     let mut names: Vec<String> = vec![];
-    if sa_flags & SA_RESTART != 0 {
-        names.push("SA_RESTART".to_string());
+    let mut rest = sa_flags;
+    for (flag, name) in [
+        (SA_NOCLDSTOP, "SA_NOCLDSTOP"),
+        (SA_NOCLDWAIT, "SA_NOCLDWAIT"),
+        (SA_SIGINFO, "SA_SIGINFO"),
+        (SA_ONSTACK, "SA_ONSTACK"),
+        (SA_RESTART, "SA_RESTART"),
+        (SA_NODEFER, "SA_NODEFER"),
+        (SA_RESETHAND, "SA_RESETHAND"),
+        (SA_RESTORER, "SA_RESTORER"),
+    ] {
+        if sa_flags & flag != 0 {
+            names.push(name.to_string());
+            rest &= !flag;
+        }
     }
-    if sa_flags & SA_RESTORER != 0 {
-        names.push("SA_RESTORER".to_string());
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
     }
-    if names.len() > 0 {
+    if !names.is_empty() {
         names.join("|")
     } else {
         String::from("0x0")
     }
 }
 
+/// Signal set as `[SIGINT SIGCHLD]`; bit n-1 stands for signal n.
+pub fn sigset_name(set: u64) -> String {
+    let names = (1..=NSIG as u64)
+        .filter(|signum| set & (1 << (signum - 1)) != 0)
+        .map(sig_name)
+        .collect::<Vec<_>>();
+    format!("[{}]", names.join(" "))
+}
+
+/// Bit of a signal in sigset_t.
+pub fn sigmask(signum: u64) -> u64 {
+    if (1..=NSIG as u64).contains(&signum) {
+        1 << (signum - 1)
+    } else {
+        0
+    }
+}
+
+/// Signals which can never be blocked.
+pub const UNBLOCKABLE: u64 = (1 << (SIGKILL - 1)) | (1 << (SIGSTOP - 1));
+
+/// sigset_t from a payload.
+pub fn parse_sigset(data: &[u8]) -> Option<u64> {
+    let mut buf = [0u8; 8];
+    buf.clone_from_slice(data.get(..8)?);
+    Some(u64::from_ne_bytes(buf))
+}
+
 pub fn sig_name(signum: u64) -> String {
     match signum {
         1 => "SIGHUP".to_string(),
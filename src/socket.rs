@@ -0,0 +1,304 @@
+//! Socket const and sockaddr decoding.
+
+use std::ffi::CStr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Address families
+pub const AF_UNSPEC: u64 = 0;
+pub const AF_UNIX: u64 = 1;
+pub const AF_INET: u64 = 2;
+pub const AF_INET6: u64 = 10;
+pub const AF_NETLINK: u64 = 16;
+pub const AF_PACKET: u64 = 17;
+
+/// Socket types
+pub const SOCK_STREAM: u64 = 1;
+pub const SOCK_DGRAM: u64 = 2;
+pub const SOCK_RAW: u64 = 3;
+pub const SOCK_SEQPACKET: u64 = 5;
+/// Socket type flags, shared with O_NONBLOCK/O_CLOEXEC.
+pub const SOCK_NONBLOCK: u64 = 0o4000;
+pub const SOCK_CLOEXEC: u64 = 0o2000000;
+const SOCK_TYPE_MASK: u64 = 0xf;
+
+/// Protocols
+pub const IPPROTO_IP: u64 = 0;
+pub const IPPROTO_ICMP: u64 = 1;
+pub const IPPROTO_TCP: u64 = 6;
+pub const IPPROTO_UDP: u64 = 17;
+pub const IPPROTO_IPV6: u64 = 41;
+pub const IPPROTO_ICMPV6: u64 = 58;
+
+/// Socket option levels
+pub const SOL_SOCKET: u64 = 1;
+
+/// SOL_SOCKET options
+pub const SO_DEBUG: u64 = 1;
+pub const SO_REUSEADDR: u64 = 2;
+pub const SO_TYPE: u64 = 3;
+pub const SO_ERROR: u64 = 4;
+pub const SO_DONTROUTE: u64 = 5;
+pub const SO_BROADCAST: u64 = 6;
+pub const SO_SNDBUF: u64 = 7;
+pub const SO_RCVBUF: u64 = 8;
+pub const SO_KEEPALIVE: u64 = 9;
+pub const SO_OOBINLINE: u64 = 10;
+pub const SO_LINGER: u64 = 13;
+pub const SO_REUSEPORT: u64 = 15;
+pub const SO_PASSCRED: u64 = 16;
+pub const SO_PEERCRED: u64 = 17;
+pub const SO_RCVLOWAT: u64 = 18;
+pub const SO_SNDLOWAT: u64 = 19;
+pub const SO_RCVTIMEO: u64 = 20;
+pub const SO_SNDTIMEO: u64 = 21;
+pub const SO_ACCEPTCONN: u64 = 30;
+pub const SO_PROTOCOL: u64 = 38;
+pub const SO_DOMAIN: u64 = 39;
+
+/// IPPROTO_TCP options
+pub const TCP_NODELAY: u64 = 1;
+pub const TCP_MAXSEG: u64 = 2;
+pub const TCP_KEEPIDLE: u64 = 4;
+pub const TCP_KEEPINTVL: u64 = 5;
+pub const TCP_KEEPCNT: u64 = 6;
+
+/// IPPROTO_IPV6 options
+pub const IPV6_V6ONLY: u64 = 26;
+
+/// shutdown how
+pub const SHUT_RD: u64 = 0;
+pub const SHUT_WR: u64 = 1;
+pub const SHUT_RDWR: u64 = 2;
+
+/// send/recv flags
+pub const MSG_OOB: u64 = 0x1;
+pub const MSG_PEEK: u64 = 0x2;
+pub const MSG_DONTROUTE: u64 = 0x4;
+pub const MSG_TRUNC: u64 = 0x20;
+pub const MSG_DONTWAIT: u64 = 0x40;
+pub const MSG_EOR: u64 = 0x80;
+pub const MSG_WAITALL: u64 = 0x100;
+pub const MSG_NOSIGNAL: u64 = 0x4000;
+
+const UNIX_PATH_MAX: usize = 108;
+
+pub fn domain_name(domain: u64) -> String {
+    match domain {
+        AF_UNSPEC => "AF_UNSPEC".to_string(),
+        AF_UNIX => "AF_UNIX".to_string(),
+        AF_INET => "AF_INET".to_string(),
+        AF_INET6 => "AF_INET6".to_string(),
+        AF_NETLINK => "AF_NETLINK".to_string(),
+        AF_PACKET => "AF_PACKET".to_string(),
+        _ => format!("{:#x}", domain),
+    }
+}
+
+/// generate socket type name, including SOCK_NONBLOCK/SOCK_CLOEXEC
+pub fn sock_type_name(ty: u64) -> String {
+    let mut names: Vec<String> = vec![];
+    match ty & SOCK_TYPE_MASK {
+        SOCK_STREAM => names.push("SOCK_STREAM".to_string()),
+        SOCK_DGRAM => names.push("SOCK_DGRAM".to_string()),
+        SOCK_RAW => names.push("SOCK_RAW".to_string()),
+        SOCK_SEQPACKET => names.push("SOCK_SEQPACKET".to_string()),
+        other => names.push(format!("{:#x}", other)),
+    }
+    if ty & SOCK_NONBLOCK != 0 {
+        names.push("SOCK_NONBLOCK".to_string());
+    }
+    if ty & SOCK_CLOEXEC != 0 {
+        names.push("SOCK_CLOEXEC".to_string());
+    }
+    names.join("|")
+}
+
+/// flags of accept4 are the type flags of socket
+pub fn accept_flag_name(flags: u64) -> String {
+    let mut names: Vec<String> = vec![];
+    if flags & SOCK_NONBLOCK != 0 {
+        names.push("SOCK_NONBLOCK".to_string());
+    }
+    if flags & SOCK_CLOEXEC != 0 {
+        names.push("SOCK_CLOEXEC".to_string());
+    }
+    if !names.is_empty() {
+        names.join("|")
+    } else {
+        String::from("0")
+    }
+}
+
+pub fn protocol_name(protocol: u64) -> String {
+    match protocol {
+        IPPROTO_IP => "IPPROTO_IP".to_string(),
+        IPPROTO_ICMP => "IPPROTO_ICMP".to_string(),
+        IPPROTO_TCP => "IPPROTO_TCP".to_string(),
+        IPPROTO_UDP => "IPPROTO_UDP".to_string(),
+        IPPROTO_IPV6 => "IPPROTO_IPV6".to_string(),
+        IPPROTO_ICMPV6 => "IPPROTO_ICMPV6".to_string(),
+        _ => format!("{:#x}", protocol),
+    }
+}
+
+pub fn sockopt_level_name(level: u64) -> String {
+    match level {
+        SOL_SOCKET => "SOL_SOCKET".to_string(),
+        _ => protocol_name(level),
+    }
+}
+
+pub fn sockopt_name(level: u64, optname: u64) -> String {
+    let name = match (level, optname) {
+        (SOL_SOCKET, SO_DEBUG) => "SO_DEBUG",
+        (SOL_SOCKET, SO_REUSEADDR) => "SO_REUSEADDR",
+        (SOL_SOCKET, SO_TYPE) => "SO_TYPE",
+        (SOL_SOCKET, SO_ERROR) => "SO_ERROR",
+        (SOL_SOCKET, SO_DONTROUTE) => "SO_DONTROUTE",
+        (SOL_SOCKET, SO_BROADCAST) => "SO_BROADCAST",
+        (SOL_SOCKET, SO_SNDBUF) => "SO_SNDBUF",
+        (SOL_SOCKET, SO_RCVBUF) => "SO_RCVBUF",
+        (SOL_SOCKET, SO_KEEPALIVE) => "SO_KEEPALIVE",
+        (SOL_SOCKET, SO_OOBINLINE) => "SO_OOBINLINE",
+        (SOL_SOCKET, SO_LINGER) => "SO_LINGER",
+        (SOL_SOCKET, SO_REUSEPORT) => "SO_REUSEPORT",
+        (SOL_SOCKET, SO_PASSCRED) => "SO_PASSCRED",
+        (SOL_SOCKET, SO_PEERCRED) => "SO_PEERCRED",
+        (SOL_SOCKET, SO_RCVLOWAT) => "SO_RCVLOWAT",
+        (SOL_SOCKET, SO_SNDLOWAT) => "SO_SNDLOWAT",
+        (SOL_SOCKET, SO_RCVTIMEO) => "SO_RCVTIMEO",
+        (SOL_SOCKET, SO_SNDTIMEO) => "SO_SNDTIMEO",
+        (SOL_SOCKET, SO_ACCEPTCONN) => "SO_ACCEPTCONN",
+        (SOL_SOCKET, SO_PROTOCOL) => "SO_PROTOCOL",
+        (SOL_SOCKET, SO_DOMAIN) => "SO_DOMAIN",
+        (IPPROTO_TCP, TCP_NODELAY) => "TCP_NODELAY",
+        (IPPROTO_TCP, TCP_MAXSEG) => "TCP_MAXSEG",
+        (IPPROTO_TCP, TCP_KEEPIDLE) => "TCP_KEEPIDLE",
+        (IPPROTO_TCP, TCP_KEEPINTVL) => "TCP_KEEPINTVL",
+        (IPPROTO_TCP, TCP_KEEPCNT) => "TCP_KEEPCNT",
+        (IPPROTO_IPV6, IPV6_V6ONLY) => "IPV6_V6ONLY",
+        _ => return format!("{:#x}", optname),
+    };
+    name.to_string()
+}
+
+/// Render an option buffer. Most options are a single int.
+pub fn sockopt_value(level: u64, optname: u64, data: &[u8]) -> String {
+    match (level, optname) {
+        (SOL_SOCKET, SO_LINGER) if data.len() >= 8 => {
+            format!("{{onoff={}, linger={}}}", read_i32(data, 0), read_i32(data, 4))
+        },
+        (SOL_SOCKET, SO_RCVTIMEO) | (SOL_SOCKET, SO_SNDTIMEO) if data.len() >= 16 => {
+            format!("{{sec={}, usec={}}}", read_i64(data, 0), read_i64(data, 8))
+        },
+        (SOL_SOCKET, SO_PEERCRED) if data.len() >= 12 => {
+            format!("{{pid={}, uid={}, gid={}}}",
+                read_i32(data, 0), read_i32(data, 4), read_i32(data, 8))
+        },
+        (SOL_SOCKET, SO_TYPE) if data.len() >= 4 => {
+            sock_type_name(read_i32(data, 0) as u64)
+        },
+        (SOL_SOCKET, SO_DOMAIN) if data.len() >= 4 => {
+            domain_name(read_i32(data, 0) as u64)
+        },
+        _ if data.len() == 4 => format!("[{}]", read_i32(data, 0)),
+        _ => format!("{:?}", data),
+    }
+}
+
+pub fn shutdown_how_name(how: u64) -> String {
+    match how {
+        SHUT_RD => "SHUT_RD".to_string(),
+        SHUT_WR => "SHUT_WR".to_string(),
+        SHUT_RDWR => "SHUT_RDWR".to_string(),
+        _ => format!("{:#x}", how),
+    }
+}
+
+pub fn msg_flag_name(flags: u64) -> String {
+    let mut names: Vec<String> = vec![];
+    if flags & MSG_OOB != 0 {
+        names.push("MSG_OOB".to_string());
+    }
+    if flags & MSG_PEEK != 0 {
+        names.push("MSG_PEEK".to_string());
+    }
+    if flags & MSG_DONTROUTE != 0 {
+        names.push("MSG_DONTROUTE".to_string());
+    }
+    if flags & MSG_TRUNC != 0 {
+        names.push("MSG_TRUNC".to_string());
+    }
+    if flags & MSG_DONTWAIT != 0 {
+        names.push("MSG_DONTWAIT".to_string());
+    }
+    if flags & MSG_EOR != 0 {
+        names.push("MSG_EOR".to_string());
+    }
+    if flags & MSG_WAITALL != 0 {
+        names.push("MSG_WAITALL".to_string());
+    }
+    if flags & MSG_NOSIGNAL != 0 {
+        names.push("MSG_NOSIGNAL".to_string());
+    }
+    if !names.is_empty() {
+        names.join("|")
+    } else {
+        String::from("0")
+    }
+}
+
+/// Decode sockaddr_in/sockaddr_in6/sockaddr_un by its family.
+pub fn sockaddr_name(data: &[u8]) -> String {
+    if data.len() < 2 {
+        return "{?}".to_string();
+    }
+    let family = u16::from_ne_bytes([data[0], data[1]]) as u64;
+    match family {
+        AF_INET if data.len() >= 8 => {
+            let port = u16::from_be_bytes([data[2], data[3]]);
+            let addr = Ipv4Addr::new(data[4], data[5], data[6], data[7]);
+            format!("{{AF_INET, {}:{}}}", addr, port)
+        },
+        AF_INET6 if data.len() >= 28 => {
+            let port = u16::from_be_bytes([data[2], data[3]]);
+            let mut octets = [0u8; 16];
+            octets.clone_from_slice(&data[8..24]);
+            let addr = Ipv6Addr::from(octets);
+            let scope_id = read_i32(data, 24) as u32;
+            if scope_id != 0 {
+                format!("{{AF_INET6, [{}%{}]:{}}}", addr, scope_id, port)
+            } else {
+                format!("{{AF_INET6, [{}]:{}}}", addr, port)
+            }
+        },
+        AF_UNIX => {
+            let path = &data[2..data.len().min(2 + UNIX_PATH_MAX)];
+            if path.is_empty() {
+                "{AF_UNIX, unnamed}".to_string()
+            } else if path[0] == 0 {
+                // Abstract namespace: the name is not nul-terminated.
+                let name = String::from_utf8_lossy(&path[1..]);
+                format!("{{AF_UNIX, @{:?}}}", name.trim_end_matches('\0'))
+            } else {
+                match CStr::from_bytes_until_nul(path) {
+                    Ok(name) => format!("{{AF_UNIX, {:?}}}", name),
+                    Err(_) => format!("{{AF_UNIX, {:?}}}", String::from_utf8_lossy(path)),
+                }
+            }
+        },
+        _ => format!("{{{}}}", domain_name(family)),
+    }
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    let mut buf = [0u8; 4];
+    buf.clone_from_slice(&data[offset..offset + 4]);
+    i32::from_ne_bytes(buf)
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    let mut buf = [0u8; 8];
+    buf.clone_from_slice(&data[offset..offset + 8]);
+    i64::from_ne_bytes(buf)
+}
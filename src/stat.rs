@@ -0,0 +1,341 @@
+//! stat/statx/statfs structs.
+//!
+//! Fields which differ between boots or filesystems (dev, ino, blksize,
+//! timestamps, ...) are marked volatile and shown by name only at level 2.
+
+use std::mem;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct KStat {
+    st_dev: u64,
+    st_ino: u64,
+    st_mode: u32,
+    st_nlink: u32,
+    st_uid: u32,
+    st_gid: u32,
+    st_rdev: u64,
+    _pad0: u64,
+    st_size: u64,
+    st_blksize: u32,
+    _pad1: u32,
+    st_blocks: u64,
+    st_atime_sec: isize,
+    st_atime_nsec: isize,
+    st_mtime_sec: isize,
+    st_mtime_nsec: isize,
+    st_ctime_sec: isize,
+    st_ctime_nsec: isize,
+}
+const KSTAT_SIZE: usize = mem::size_of::<KStat>();
+
+#[derive(Debug)]
+#[repr(C)]
+struct StatxTimestamp {
+    tv_sec: i64,
+    tv_nsec: u32,
+    _reserved: i32,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct Statx {
+    stx_mask: u32,
+    stx_blksize: u32,
+    stx_attributes: u64,
+    stx_nlink: u32,
+    stx_uid: u32,
+    stx_gid: u32,
+    stx_mode: u16,
+    _spare0: u16,
+    stx_ino: u64,
+    stx_size: u64,
+    stx_blocks: u64,
+    stx_attributes_mask: u64,
+    stx_atime: StatxTimestamp,
+    stx_btime: StatxTimestamp,
+    stx_ctime: StatxTimestamp,
+    stx_mtime: StatxTimestamp,
+    stx_rdev_major: u32,
+    stx_rdev_minor: u32,
+    stx_dev_major: u32,
+    stx_dev_minor: u32,
+    stx_mnt_id: u64,
+    _spare2: u64,
+    _spare3: [u64; 12],
+}
+const STRUCT_STATX_SIZE: usize = mem::size_of::<Statx>();
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct StatFs {
+    f_type: i64,
+    f_bsize: i64,
+    f_blocks: u64,
+    f_bfree: u64,
+    f_bavail: u64,
+    f_files: u64,
+    f_ffree: u64,
+    f_fsid: [i32; 2],
+    f_namelen: i64,
+    f_frsize: i64,
+    f_flags: i64,
+    _spare: [i64; 4],
+}
+const STATFS_SIZE: usize = mem::size_of::<StatFs>();
+
+/// statx mask
+pub const STATX_TYPE: u32 = 0x1;
+pub const STATX_MODE: u32 = 0x2;
+pub const STATX_NLINK: u32 = 0x4;
+pub const STATX_UID: u32 = 0x8;
+pub const STATX_GID: u32 = 0x10;
+pub const STATX_ATIME: u32 = 0x20;
+pub const STATX_MTIME: u32 = 0x40;
+pub const STATX_CTIME: u32 = 0x80;
+pub const STATX_INO: u32 = 0x100;
+pub const STATX_SIZE: u32 = 0x200;
+pub const STATX_BLOCKS: u32 = 0x400;
+pub const STATX_BASIC_STATS: u32 = 0x7ff;
+pub const STATX_BTIME: u32 = 0x800;
+pub const STATX_MNT_ID: u32 = 0x1000;
+
+/// *at flags
+pub const AT_SYMLINK_NOFOLLOW: u64 = 0x100;
+pub const AT_REMOVEDIR: u64 = 0x200;
+/// Of faccessat2, the same bit as AT_REMOVEDIR
+pub const AT_EACCESS: u64 = 0x200;
+pub const AT_SYMLINK_FOLLOW: u64 = 0x400;
+pub const AT_NO_AUTOMOUNT: u64 = 0x800;
+pub const AT_EMPTY_PATH: u64 = 0x1000;
+pub const AT_STATX_FORCE_SYNC: u64 = 0x2000;
+pub const AT_STATX_DONT_SYNC: u64 = 0x4000;
+
+/// utimensat special nsec
+pub const UTIME_NOW: i64 = (1 << 30) - 1;
+pub const UTIME_OMIT: i64 = (1 << 30) - 2;
+
+/// One rendered field of a struct.
+struct Field {
+    name: &'static str,
+    value: String,
+    volatile: bool,
+}
+
+impl Field {
+    fn new(name: &'static str, value: String) -> Self {
+        Self { name, value, volatile: false }
+    }
+
+    fn volatile(name: &'static str, value: String) -> Self {
+        Self { name, value, volatile: true }
+    }
+}
+
+fn render_fields(fields: &[Field], level: usize) -> String {
+    let fields = fields.iter().map(|field| {
+        if field.volatile && level == 2 {
+            field.name.to_string()
+        } else {
+            format!("{}={}", field.name, field.value)
+        }
+    }).collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(", "))
+}
+
+pub fn kstat_name(data: &[u8], level: usize) -> String {
+    if data.len() < KSTAT_SIZE {
+        return format!("{:?}", data);
+    }
+    let mut buf = [0u8; KSTAT_SIZE];
+    buf.clone_from_slice(&data[..KSTAT_SIZE]);
+
+    let k = unsafe { mem::transmute::<[u8; KSTAT_SIZE], KStat>(buf) };
+    render_fields(&[
+        Field::volatile("dev", format!("{:#x}", k.st_dev)),
+        Field::volatile("ino", format!("{}", k.st_ino)),
+        Field::new("mode", format!("{:#o}", k.st_mode)),
+        Field::new("nlink", format!("{}", k.st_nlink)),
+        Field::new("rdev", format!("{}", k.st_rdev)),
+        Field::new("size", format!("{}", k.st_size)),
+        Field::volatile("blksize", format!("{}", k.st_blksize)),
+        Field::new("blocks", format!("{}", k.st_blocks)),
+    ], level)
+}
+
+pub fn statx_mask_name(mask: u32) -> String {
+    let mut names: Vec<String> = vec![];
+    let mut rest = mask;
+    if mask & STATX_BASIC_STATS == STATX_BASIC_STATS {
+        names.push("STATX_BASIC_STATS".to_string());
+        rest &= !STATX_BASIC_STATS;
+    }
+    for (bit, name) in [
+        (STATX_TYPE, "STATX_TYPE"),
+        (STATX_MODE, "STATX_MODE"),
+        (STATX_NLINK, "STATX_NLINK"),
+        (STATX_UID, "STATX_UID"),
+        (STATX_GID, "STATX_GID"),
+        (STATX_ATIME, "STATX_ATIME"),
+        (STATX_MTIME, "STATX_MTIME"),
+        (STATX_CTIME, "STATX_CTIME"),
+        (STATX_INO, "STATX_INO"),
+        (STATX_SIZE, "STATX_SIZE"),
+        (STATX_BLOCKS, "STATX_BLOCKS"),
+        (STATX_BTIME, "STATX_BTIME"),
+        (STATX_MNT_ID, "STATX_MNT_ID"),
+    ] {
+        if rest & bit != 0 {
+            names.push(name.to_string());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    if !names.is_empty() {
+        names.join("|")
+    } else {
+        String::from("0")
+    }
+}
+
+fn timestamp_name(ts: &StatxTimestamp) -> String {
+    format!("{}.{:09}", ts.tv_sec, ts.tv_nsec)
+}
+
+/// Only the fields the kernel reported as filled in stx_mask are shown.
+pub fn statx_name(data: &[u8], level: usize) -> String {
+    if data.len() < STRUCT_STATX_SIZE {
+        return format!("{:?}", data);
+    }
+    let mut buf = [0u8; STRUCT_STATX_SIZE];
+    buf.clone_from_slice(&data[..STRUCT_STATX_SIZE]);
+
+    let s = unsafe { mem::transmute::<[u8; STRUCT_STATX_SIZE], Statx>(buf) };
+    let mask = s.stx_mask;
+    let mut fields = vec![Field::new("mask", statx_mask_name(mask))];
+    // blksize, rdev and dev have no mask bit: statx(2) always fills them.
+    fields.push(Field::volatile("blksize", format!("{}", s.stx_blksize)));
+    if mask & (STATX_TYPE | STATX_MODE) != 0 {
+        fields.push(Field::new("mode", format!("{:#o}", s.stx_mode)));
+    }
+    if mask & STATX_NLINK != 0 {
+        fields.push(Field::new("nlink", format!("{}", s.stx_nlink)));
+    }
+    if mask & STATX_UID != 0 {
+        fields.push(Field::new("uid", format!("{}", s.stx_uid)));
+    }
+    if mask & STATX_GID != 0 {
+        fields.push(Field::new("gid", format!("{}", s.stx_gid)));
+    }
+    if mask & STATX_INO != 0 {
+        fields.push(Field::volatile("ino", format!("{}", s.stx_ino)));
+    }
+    if mask & STATX_SIZE != 0 {
+        fields.push(Field::new("size", format!("{}", s.stx_size)));
+    }
+    if mask & STATX_BLOCKS != 0 {
+        fields.push(Field::new("blocks", format!("{}", s.stx_blocks)));
+    }
+    if mask & STATX_ATIME != 0 {
+        fields.push(Field::volatile("atime", timestamp_name(&s.stx_atime)));
+    }
+    if mask & STATX_BTIME != 0 {
+        fields.push(Field::volatile("btime", timestamp_name(&s.stx_btime)));
+    }
+    if mask & STATX_CTIME != 0 {
+        fields.push(Field::volatile("ctime", timestamp_name(&s.stx_ctime)));
+    }
+    if mask & STATX_MTIME != 0 {
+        fields.push(Field::volatile("mtime", timestamp_name(&s.stx_mtime)));
+    }
+    fields.push(Field::new("rdev", format!("{}:{}", s.stx_rdev_major, s.stx_rdev_minor)));
+    fields.push(Field::volatile("dev", format!("{}:{}", s.stx_dev_major, s.stx_dev_minor)));
+    if mask & STATX_MNT_ID != 0 {
+        fields.push(Field::volatile("mnt_id", format!("{}", s.stx_mnt_id)));
+    }
+    render_fields(&fields, level)
+}
+
+pub fn statfs_name(data: &[u8], level: usize) -> String {
+    if data.len() < STATFS_SIZE {
+        return format!("{:?}", data);
+    }
+    let mut buf = [0u8; STATFS_SIZE];
+    buf.clone_from_slice(&data[..STATFS_SIZE]);
+
+    let f = unsafe { mem::transmute::<[u8; STATFS_SIZE], StatFs>(buf) };
+    render_fields(&[
+        Field::new("type", format!("{:#x}", f.f_type)),
+        Field::volatile("bsize", format!("{}", f.f_bsize)),
+        Field::volatile("blocks", format!("{}", f.f_blocks)),
+        Field::volatile("bfree", format!("{}", f.f_bfree)),
+        Field::volatile("bavail", format!("{}", f.f_bavail)),
+        Field::volatile("files", format!("{}", f.f_files)),
+        Field::volatile("ffree", format!("{}", f.f_ffree)),
+        Field::volatile("fsid", format!("{{{:#x}, {:#x}}}", f.f_fsid[0], f.f_fsid[1])),
+        Field::new("namelen", format!("{}", f.f_namelen)),
+        Field::volatile("frsize", format!("{}", f.f_frsize)),
+        Field::new("flags", format!("{:#x}", f.f_flags)),
+    ], level)
+}
+
+pub fn at_flag_name(flags: u64) -> String {
+    let mut names: Vec<String> = vec![];
+    let mut rest = flags;
+    for (bit, name) in [
+        (AT_SYMLINK_NOFOLLOW, "AT_SYMLINK_NOFOLLOW"),
+        (AT_REMOVEDIR, "AT_REMOVEDIR"),
+        (AT_SYMLINK_FOLLOW, "AT_SYMLINK_FOLLOW"),
+        (AT_NO_AUTOMOUNT, "AT_NO_AUTOMOUNT"),
+        (AT_EMPTY_PATH, "AT_EMPTY_PATH"),
+        (AT_STATX_FORCE_SYNC, "AT_STATX_FORCE_SYNC"),
+        (AT_STATX_DONT_SYNC, "AT_STATX_DONT_SYNC"),
+    ] {
+        if flags & bit != 0 {
+            names.push(name.to_string());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    if !names.is_empty() {
+        names.join("|")
+    } else {
+        String::from("0")
+    }
+}
+
+/// Flags of faccessat2.
+pub fn access_flag_name(flags: u64) -> String {
+    if flags & AT_EACCESS == 0 {
+        return at_flag_name(flags);
+    }
+    match flags & !AT_EACCESS {
+        0 => String::from("AT_EACCESS"),
+        rest => format!("AT_EACCESS|{}", at_flag_name(rest)),
+    }
+}
+
+/// `struct timespec[2]` of utimensat; timestamps are volatile unless special.
+pub fn utimens_name(data: &[u8], level: usize) -> String {
+    if data.len() < 32 {
+        return format!("{:?}", data);
+    }
+    let times = data[..32].chunks_exact(16).map(|chunk| {
+        let mut buf = [0u8; 8];
+        buf.clone_from_slice(&chunk[..8]);
+        let sec = i64::from_ne_bytes(buf);
+        buf.clone_from_slice(&chunk[8..16]);
+        let nsec = i64::from_ne_bytes(buf);
+        match nsec {
+            UTIME_NOW => "UTIME_NOW".to_string(),
+            UTIME_OMIT => "UTIME_OMIT".to_string(),
+            _ if level == 2 => "time".to_string(),
+            _ => format!("{}.{:09}", sec, nsec),
+        }
+    }).collect::<Vec<_>>();
+    format!("[{}]", times.join(", "))
+}
@@ -1,14 +1,20 @@
-///
-/// Linux syscall
-///
+//!
+//! Linux syscall
+//!
 
 pub const SYS_GETCWD: u64 = 0x11;
+pub const SYS_EVENTFD2: u64 = 0x13;
+pub const SYS_EPOLL_CREATE1: u64 = 0x14;
+pub const SYS_EPOLL_CTL: u64 = 0x15;
+pub const SYS_EPOLL_PWAIT: u64 = 0x16;
 pub const SYS_DUP3: u64 = 0x18;
 pub const SYS_FCNTL: u64 = 0x19;
 pub const SYS_IOCTL: u64 = 0x1d;
 pub const SYS_MKDIRAT: u64 = 0x22;
 pub const SYS_UNLINKAT: u64 = 0x23;
 pub const SYS_MOUNT: u64 = 0x28;
+pub const SYS_STATFS: u64 = 0x2b;
+pub const SYS_FSTATFS: u64 = 0x2c;
 pub const SYS_FTRUNCATE: u64 = 0x2e;
 pub const SYS_FACCESSAT: u64 = 0x30;
 pub const SYS_CHDIR: u64 = 0x31;
@@ -17,6 +23,7 @@ pub const SYS_FCHMODAT: u64 = 0x35;
 pub const SYS_FCHOWNAT: u64 = 0x36;
 pub const SYS_OPENAT: u64 = 0x38;
 pub const SYS_CLOSE: u64 = 0x39;
+pub const SYS_PIPE2: u64 = 0x3b;
 pub const SYS_GETDENTS64: u64 = 0x3d;
 pub const SYS_LSEEK: u64 = 0x3e;
 pub const SYS_READ: u64 = 0x3f;
@@ -24,11 +31,16 @@ pub const SYS_WRITE: u64 = 0x40;
 pub const SYS_WRITEV: u64 = 0x42;
 pub const SYS_PREAD64: u64 = 0x43;
 pub const SYS_SENDFILE: u64 = 0x47;
+pub const SYS_PSELECT6: u64 = 0x48;
+pub const SYS_PPOLL: u64 = 0x49;
 pub const SYS_READLINKAT: u64 = 0x4e;
 pub const SYS_FSTATAT: u64 = 0x4f;
+pub const SYS_FSTAT: u64 = 0x50;
+pub const SYS_UTIMENSAT: u64 = 0x58;
 pub const SYS_CAPGET: u64 = 0x5a;
 pub const SYS_EXIT: u64 = 0x5d;
 pub const SYS_EXIT_GROUP: u64 = 0x5e;
+pub const SYS_WAITID: u64 = 0x5f;
 pub const SYS_SETITIMER: u64 = 0x67;
 pub const SYS_TGKILL: u64 = 0x83;
 
@@ -46,6 +58,37 @@ pub const SYS_GETEUID: u64 = 0xaf;
 pub const SYS_GETGID: u64 = 0xb0;
 pub const SYS_GETEGID: u64 = 0xb1;
 pub const SYS_GETTID: u64 = 0xb2;
+pub const SYS_MQ_OPEN: u64 = 0xb4;
+pub const SYS_MQ_UNLINK: u64 = 0xb5;
+pub const SYS_MQ_TIMEDSEND: u64 = 0xb6;
+pub const SYS_MQ_TIMEDRECEIVE: u64 = 0xb7;
+pub const SYS_MQ_NOTIFY: u64 = 0xb8;
+pub const SYS_MQ_GETSETATTR: u64 = 0xb9;
+pub const SYS_MSGGET: u64 = 0xba;
+pub const SYS_MSGCTL: u64 = 0xbb;
+pub const SYS_MSGRCV: u64 = 0xbc;
+pub const SYS_MSGSND: u64 = 0xbd;
+pub const SYS_SEMGET: u64 = 0xbe;
+pub const SYS_SEMCTL: u64 = 0xbf;
+pub const SYS_SEMTIMEDOP: u64 = 0xc0;
+pub const SYS_SEMOP: u64 = 0xc1;
+pub const SYS_SHMGET: u64 = 0xc2;
+pub const SYS_SHMCTL: u64 = 0xc3;
+pub const SYS_SHMAT: u64 = 0xc4;
+pub const SYS_SHMDT: u64 = 0xc5;
+pub const SYS_SOCKET: u64 = 0xc6;
+pub const SYS_SOCKETPAIR: u64 = 0xc7;
+pub const SYS_BIND: u64 = 0xc8;
+pub const SYS_LISTEN: u64 = 0xc9;
+pub const SYS_ACCEPT: u64 = 0xca;
+pub const SYS_CONNECT: u64 = 0xcb;
+pub const SYS_GETSOCKNAME: u64 = 0xcc;
+pub const SYS_GETPEERNAME: u64 = 0xcd;
+pub const SYS_SENDTO: u64 = 0xce;
+pub const SYS_RECVFROM: u64 = 0xcf;
+pub const SYS_SETSOCKOPT: u64 = 0xd0;
+pub const SYS_GETSOCKOPT: u64 = 0xd1;
+pub const SYS_SHUTDOWN: u64 = 0xd2;
 pub const SYS_BRK: u64 = 0xd6;
 pub const SYS_MUNMAP: u64 = 0xd7;
 pub const SYS_CLONE: u64 = 0xdc;
@@ -55,12 +98,18 @@ pub const SYS_MMAP: u64 = 0xde;
 pub const SYS_MPROTECT: u64 = 0xe2;
 pub const SYS_MSYNC: u64 = 0xe3;
 pub const SYS_MADVISE: u64 = 0xe9;
+pub const SYS_ACCEPT4: u64 = 0xf2;
 pub const SYS_WAIT4: u64 = 0x104;
 pub const SYS_PRLIMIT64: u64 = 0x105;
 pub const SYS_GETRANDOM: u64 = 0x116;
+pub const SYS_MEMFD_CREATE: u64 = 0x117;
 pub const SYS_RSEQ: u64 = 0x125;
+pub const SYS_STATX: u64 = 0x123;
+pub const SYS_CLONE3: u64 = 0x1b3;
+pub const SYS_FACCESSAT2: u64 = 0x1b7;
 
 pub const SYS_SET_TID_ADDRESS: u64 = 0x60;
+pub const SYS_FUTEX: u64 = 0x62;
 pub const SYS_SET_ROBUST_LIST: u64 = 0x63;
 pub const SYS_CLOCK_GETTIME: u64 = 0x71;
 pub const SYS_CLOCK_NANOSLEEP: u64 = 0x73;
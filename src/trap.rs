@@ -0,0 +1,84 @@
+//! RISC-V trap causes other than ecall.
+//!
+//! A trap record has the same head as a syscall record, with scause in
+//! `cause`. The tracer may attach stval as payload 0.
+
+use crate::event::TraceEvent;
+
+/// Interrupt bit of scause
+pub const CAUSE_INTERRUPT: u64 = 1 << 63;
+
+/// exceptions
+pub const INSN_MISALIGNED: u64 = 0;
+pub const INSN_ACCESS_FAULT: u64 = 1;
+pub const ILLEGAL_INSN: u64 = 2;
+pub const BREAKPOINT: u64 = 3;
+pub const LOAD_MISALIGNED: u64 = 4;
+pub const LOAD_ACCESS_FAULT: u64 = 5;
+pub const STORE_MISALIGNED: u64 = 6;
+pub const STORE_ACCESS_FAULT: u64 = 7;
+pub const INSN_PAGE_FAULT: u64 = 12;
+pub const LOAD_PAGE_FAULT: u64 = 13;
+pub const STORE_PAGE_FAULT: u64 = 15;
+
+/// interrupts
+pub const IRQ_S_SOFT: u64 = 1;
+pub const IRQ_S_TIMER: u64 = 5;
+pub const IRQ_S_EXT: u64 = 9;
+
+/// Payload index of stval
+const STVAL_INDEX: usize = 0;
+
+pub fn is_interrupt(cause: u64) -> bool {
+    cause & CAUSE_INTERRUPT != 0
+}
+
+pub fn cause_name(cause: u64) -> String {
+    let code = cause & !CAUSE_INTERRUPT;
+    let name = if is_interrupt(cause) {
+        match code {
+            IRQ_S_SOFT => "software_interrupt",
+            IRQ_S_TIMER => "timer_interrupt",
+            IRQ_S_EXT => "external_interrupt",
+            _ => return format!("interrupt_{}", code),
+        }
+    } else {
+        match code {
+            INSN_MISALIGNED => "instruction_misaligned",
+            INSN_ACCESS_FAULT => "instruction_access_fault",
+            ILLEGAL_INSN => "illegal_instruction",
+            BREAKPOINT => "breakpoint",
+            LOAD_MISALIGNED => "load_misaligned",
+            LOAD_ACCESS_FAULT => "load_access_fault",
+            STORE_MISALIGNED => "store_misaligned",
+            STORE_ACCESS_FAULT => "store_access_fault",
+            INSN_PAGE_FAULT => "instruction_page_fault",
+            LOAD_PAGE_FAULT => "load_page_fault",
+            STORE_PAGE_FAULT => "store_page_fault",
+            _ => return format!("exception_{}", code),
+        }
+    };
+    name.to_string()
+}
+
+/// stval of the trap, if recorded.
+pub fn stval(evt: &TraceEvent) -> Option<u64> {
+    let payload = evt.find_payload(STVAL_INDEX)?;
+    let mut buf = [0u8; 8];
+    buf.clone_from_slice(payload.data.get(..8)?);
+    Some(u64::from_ne_bytes(buf))
+}
+
+/// `load_page_fault(addr=0x...)`; stval of illegal instruction is the
+/// instruction itself.
+pub fn trap_name(evt: &TraceEvent) -> String {
+    let cause = evt.head.cause;
+    let name = cause_name(cause);
+    match stval(evt) {
+        Some(stval) if !is_interrupt(cause) => {
+            let field = if cause == ILLEGAL_INSN { "insn" } else { "addr" };
+            format!("{}({}={:#x})", name, field, stval)
+        },
+        _ => name,
+    }
+}
@@ -0,0 +1,203 @@
+//! Syscalls which lkmodel has not implemented.
+//!
+//! A call of lkmodel is listed if it fails with ENOSYS, if it fails and the
+//! decoder does not even name it (`sys_<n>`), or if it fails with EINVAL
+//! where Linux has succeeded. Without a Linux trace, EINVAL is not listed;
+//! with one, failures which Linux shares at the same point are left out.
+//! The point is found by aligning the events as `lkdiff diff` does, with
+//! or without traps.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use crate::align::{align_keys, EditOp, EventEq};
+use crate::decode::{decode_event, DecodedCall};
+use crate::divergence::compared;
+use crate::errno::{errno_name, EINVAL, ENOSYS, UNKNOWN_ERRNO};
+use crate::event::{SigStage, TraceEvent};
+use crate::flow::TraceFlow;
+use crate::matching::match_tasks;
+
+/// Distinct argument lists shown for each syscall.
+const MAX_CALLS_SHOWN: usize = 5;
+
+/// Calls of one syscall failed with one errno.
+pub struct Unimplemented {
+    /// Syscall name, or `sys_<n>`
+    pub name: String,
+    pub sysno: u64,
+    pub errno: i32,
+    pub count: usize,
+    /// epc of the first call
+    pub epc: u64,
+    /// Task and index of the first call
+    pub task: String,
+    pub index: usize,
+    /// Distinct `name(args)`
+    pub calls: Vec<String>,
+    /// Record of the first call, for ordering
+    seq: usize,
+}
+
+/// Events of Linux aligned with each event of a lkmodel flow.
+fn linux_events<'a>(
+    lkmodel: &[TraceFlow], linux: &'a [TraceFlow], traps: bool
+) -> HashMap<(usize, usize), &'a TraceEvent> {
+    let matched = match_tasks(linux, lkmodel);
+    let eq = EventEq::default();
+    let mut events = HashMap::new();
+    for &(l, r, _) in &matched.pairs {
+        let (left, right) = (compared(&linux[l], traps), compared(&lkmodel[r], traps));
+        let keys = |flow: &TraceFlow, indexes: &[usize]| {
+            indexes.iter().map(|&i| eq.key(&flow.events[i])).collect::<Vec<_>>()
+        };
+        for op in align_keys(&keys(&linux[l], &left), &keys(&lkmodel[r], &right)) {
+            if let EditOp::Equal(i, j) | EditOp::Change(i, j) = op {
+                events.insert((r, right[j]), &linux[l].events[left[i]]);
+            }
+        }
+    }
+    events
+}
+
+/// Whether the call is reported, given the Linux call at the same point.
+fn is_unimplemented(evt: &TraceEvent, call: &DecodedCall, linux: Option<Option<&TraceEvent>>) -> bool {
+    let errno = match call.result.errno() {
+        Some(errno) => errno,
+        None => return false,
+    };
+    let undecoded = call.name == format!("sys_{}", evt.head.ax[7]);
+    let linux_errno = linux.flatten().map(|evt| decode_event(evt).result.errno());
+    match linux_errno {
+        // Linux fails the same way.
+        Some(Some(linux_errno)) if linux_errno == errno => false,
+        Some(None) if errno == EINVAL => true,
+        _ => errno == ENOSYS || undecoded,
+    }
+}
+
+/// Unimplemented syscalls of lkmodel, by count, with the Linux flows of
+/// the same app if any, aligned with traps if `traps`. Flows are ordered by
+/// start.
+pub fn unimplemented(lkmodel: &[TraceFlow], linux: Option<&[TraceFlow]>, traps: bool) -> Vec<Unimplemented> {
+    let aligned = linux.map(|linux| linux_events(lkmodel, linux, traps));
+    let mut found: HashMap<(u64, i32), Unimplemented> = HashMap::new();
+    for (r, flow) in lkmodel.iter().enumerate() {
+        for (j, evt) in flow.events.iter().enumerate() {
+            if evt.is_trap() || evt.signal != SigStage::Empty || evt.out_head.is_none() {
+                continue;
+            }
+            let call = decode_event(evt);
+            let linux = aligned.as_ref().map(|aligned| aligned.get(&(r, j)).copied());
+            if !is_unimplemented(evt, &call, linux) {
+                continue;
+            }
+            let sysno = evt.head.ax[7];
+            let errno = call.result.errno().unwrap();
+            let entry = found.entry((sysno, errno)).or_insert_with(|| Unimplemented {
+                name: call.name.clone(),
+                sysno,
+                errno,
+                count: 0,
+                epc: evt.head.epc,
+                task: flow.name(),
+                index: j,
+                calls: vec![],
+                seq: evt.seq,
+            });
+            if evt.seq < entry.seq {
+                entry.epc = evt.head.epc;
+                entry.task = flow.name();
+                entry.index = j;
+                entry.seq = evt.seq;
+            }
+            entry.count += 1;
+            let signature = call.signature();
+            if !entry.calls.contains(&signature) {
+                entry.calls.push(signature);
+            }
+        }
+    }
+    let mut found = found.into_values().collect::<Vec<_>>();
+    found.sort_by_key(|entry| (Reverse(entry.count), entry.seq));
+    found
+}
+
+pub fn print_unimplemented(found: &[Unimplemented]) {
+    println!("Unimplemented syscalls: {}", found.len());
+    for entry in found {
+        let errno = match errno_name(-entry.errno as i64) {
+            UNKNOWN_ERRNO => format!("errno {}", entry.errno),
+            name => name.to_string(),
+        };
+        println!(
+            "{} -> {}: {} calls, first at epc {:#x} in Task[{}] [{}]",
+            entry.name,
+            errno,
+            entry.count,
+            entry.epc,
+            entry.task,
+            entry.index
+        );
+        for call in entry.calls.iter().take(MAX_CALLS_SHOWN) {
+            println!("    {}", call);
+        }
+        if entry.calls.len() > MAX_CALLS_SHOWN {
+            println!("    ... {} more", entry.calls.len() - MAX_CALLS_SHOWN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::USER_ECALL;
+    use crate::sysno::*;
+    use crate::OUT;
+
+    fn call(sysno: u64, args: &[u64], result: i64) -> TraceEvent {
+        let mut evt = TraceEvent::default();
+        evt.head.inout = OUT;
+        evt.head.cause = USER_ECALL;
+        evt.head.ax[7] = sysno;
+        evt.head.ax[..args.len()].copy_from_slice(args);
+        evt.out_head = Some(evt.head.clone());
+        evt.result = result;
+        evt
+    }
+
+    fn flow(events: Vec<TraceEvent>) -> TraceFlow {
+        let mut flow = TraceFlow::new();
+        flow.events = events;
+        flow
+    }
+
+    fn names(found: &[Unimplemented]) -> Vec<(&str, i32, usize)> {
+        found.iter().map(|entry| (entry.name.as_str(), entry.errno, entry.count)).collect()
+    }
+
+    #[test]
+    fn lists_enosys_and_unknown_syscalls() {
+        let lkmodel = [flow(vec![
+            call(SYS_LSEEK, &[3, 0, 0], -ENOSYS as i64),
+            call(999, &[1], -EINVAL as i64),
+            call(SYS_LSEEK, &[4, 0, 0], -ENOSYS as i64),
+            call(SYS_LSEEK, &[3, 0, 0], -EINVAL as i64),
+            call(SYS_GETPID, &[], 0x100),
+        ])];
+        let found = unimplemented(&lkmodel, None, false);
+        assert_eq!(names(&found), [("lseek", ENOSYS, 2), ("sys_999", EINVAL, 1)]);
+        assert_eq!(found[0].calls.len(), 2);
+        assert_eq!(found[0].index, 0);
+    }
+
+    #[test]
+    fn lists_einval_where_linux_succeeds() {
+        let lseek = |fd, result| call(SYS_LSEEK, &[fd, 0, 0], result);
+        let lkmodel = [flow(vec![lseek(3, -EINVAL as i64), lseek(4, -EINVAL as i64), lseek(5, -ENOSYS as i64)])];
+        // Linux fails the second call the same way, and the third one too.
+        let linux = [flow(vec![lseek(3, 0), lseek(4, -EINVAL as i64), lseek(5, -ENOSYS as i64)])];
+        let found = unimplemented(&lkmodel, Some(&linux), false);
+        assert_eq!(names(&found), [("lseek", EINVAL, 1)]);
+        assert_eq!(found[0].calls, ["lseek(0x3, 0x0, 0x0)"]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Waivers of known and accepted divergences.
+//!
+//! A waiver file has a section per waiver, named by its id, with patterns
+//! of the divergences it waives and why:
+//!
+//! ```text
+//! # lkmodel reports its own release
+//! [uname-release]
+//! syscall = uname
+//! class = out_payload
+//! lkmodel = *release: "*lkmodel*"*
+//! reason = release of lkmodel
+//! ticket = LK-12
+//! ```
+//!
+//! The patterns are `task` (path in the process tree on either side, see
+//! [`task_paths`](crate::matching::task_paths)), `syscall`, `class` (kind
+//! of the divergence), `field`, and the values on each side, `linux` and
+//! `lkmodel`. Patterns are globs with `*` and `?`, and match anything if
+//! not given; a side without a value is empty. A value may be quoted to
+//! keep spaces at either end.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use crate::divergence::Divergence;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Waiver {
+    pub id: String,
+    pub task: String,
+    pub syscall: String,
+    pub class: String,
+    pub field: String,
+    pub linux: String,
+    pub lkmodel: String,
+    pub reason: String,
+    pub ticket: String,
+    /// Line of the section in the file
+    pub line: usize,
+}
+
+impl Waiver {
+    fn new(id: &str, line: usize) -> Self {
+        let any = || "*".to_string();
+        Self {
+            id: id.to_string(),
+            task: any(),
+            syscall: any(),
+            class: any(),
+            field: any(),
+            linux: any(),
+            lkmodel: any(),
+            reason: String::new(),
+            ticket: String::new(),
+            line,
+        }
+    }
+
+    pub fn matches(&self, div: &Divergence) -> bool {
+        let text = |val: &Option<String>| val.clone().unwrap_or_default();
+        let paths = [&div.left, &div.right].into_iter()
+            .flatten()
+            .map(|site| site.path.as_str())
+            .collect::<Vec<_>>();
+        paths.iter().any(|path| glob_match(&self.task, path))
+            && glob_match(&self.syscall, &text(&div.syscall))
+            && glob_match(&self.class, div.class.kind())
+            && glob_match(&self.field, &text(&div.field))
+            && glob_match(&self.linux, &text(&div.left_value))
+            && glob_match(&self.lkmodel, &text(&div.right_value))
+    }
+}
+
+pub fn load_waivers(fname: &str) -> Result<Vec<Waiver>> {
+    parse_waivers(&fs::read_to_string(fname)?)
+}
+
+pub fn parse_waivers(text: &str) -> Result<Vec<Waiver>> {
+    let invalid = |line: usize, msg: &str| {
+        Error::new(ErrorKind::InvalidData, format!("waivers line {}: {}", line, msg))
+    };
+    let mut waivers: Vec<Waiver> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let lineno = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(id) = line.strip_prefix('[') {
+            let id = id.strip_suffix(']').ok_or_else(|| invalid(lineno, "unterminated section"))?.trim();
+            if waivers.iter().any(|waiver| waiver.id == id) {
+                return Err(invalid(lineno, "duplicate id"));
+            }
+            waivers.push(Waiver::new(id, lineno));
+            continue;
+        }
+        let (key, val) = line.split_once('=').ok_or_else(|| invalid(lineno, "expected key = value"))?;
+        let waiver = waivers.last_mut().ok_or_else(|| invalid(lineno, "key outside of a waiver"))?;
+        let val = unquote(val.trim()).to_string();
+        match key.trim() {
+            "task" => waiver.task = val,
+            "syscall" => waiver.syscall = val,
+            "class" => waiver.class = val,
+            "field" => waiver.field = val,
+            "linux" => waiver.linux = val,
+            "lkmodel" => waiver.lkmodel = val,
+            "reason" => waiver.reason = val,
+            "ticket" => waiver.ticket = val,
+            _ => return Err(invalid(lineno, "unknown key")),
+        }
+    }
+    Ok(waivers)
+}
+
+fn unquote(val: &str) -> &str {
+    val.strip_prefix('"').and_then(|val| val.strip_suffix('"')).unwrap_or(val)
+}
+
+/// Match with `*` for any string and `?` for any char.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // Last `*` and the text position it has matched up to.
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("openat", "openat"));
+        assert!(!glob_match("openat", "openat2"));
+        assert!(glob_match("open*", "openat2"));
+        assert!(glob_match("*at", "openat"));
+        assert!(!glob_match("*at", "openat2"));
+        assert!(glob_match("0.?", "0.1"));
+        assert!(!glob_match("0.?", "0.12"));
+        assert!(glob_match("*release: \"*lkmodel*\"*", "{release: \"6.1-lkmodel\", machine: riscv64}"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYcZ"));
+        assert!(glob_match("**", "x"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn parse() {
+        let text = "# known issues\n\
+            [uname-release]\n\
+            syscall = uname\n\
+            lkmodel = \" *lkmodel* \"\n\
+            reason = release of lkmodel\n\
+            ticket = LK-12\n\
+            \n\
+            [any]\n";
+        let waivers = parse_waivers(text).unwrap();
+        assert_eq!(waivers.len(), 2);
+        let waiver = &waivers[0];
+        assert_eq!(waiver.id, "uname-release");
+        assert_eq!(waiver.line, 2);
+        assert_eq!(waiver.syscall, "uname");
+        assert_eq!(waiver.lkmodel, " *lkmodel* ");
+        assert_eq!(waiver.linux, "*");
+        assert_eq!(waiver.reason, "release of lkmodel");
+        assert_eq!(waiver.ticket, "LK-12");
+        assert_eq!(waivers[1], Waiver::new("any", 8));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let line = |text: &str| parse_waivers(text).unwrap_err().to_string();
+        assert_eq!(line("syscall = read\n"), "waivers line 1: key outside of a waiver");
+        assert_eq!(line("[a\n"), "waivers line 1: unterminated section");
+        assert_eq!(line("[a]\n[a]\n"), "waivers line 2: duplicate id");
+        assert_eq!(line("[a]\nsyscall\n"), "waivers line 2: expected key = value");
+        assert_eq!(line("[a]\nsyscal = read\n"), "waivers line 2: unknown key");
+    }
+}